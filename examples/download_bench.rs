@@ -0,0 +1,108 @@
+//! Downloads files from a local server through download_all and reports the throughput.
+//!
+//! cargo run --release --example download_bench -- [files] [workers] [latency_ms] [size_kib]
+//!
+//! The server waits latency_ms before answering each request, standing in for the
+//! round trip to a real host, so the effect of the worker count stays visible on loopback.
+
+use std::{
+    env,
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    thread,
+    time::{Duration, Instant},
+};
+
+use minecraft_launcher_lib::{
+    config,
+    download::{Download, DownloadOptions},
+    progress::NoProgress,
+    Launcher,
+};
+use url::Url;
+
+/// returns the positional argument at index, or default when it's missing
+fn get_arg(index: usize, default: usize) -> usize {
+    match env::args().nth(index) {
+        Some(arg) => arg.parse().expect("arguments must be numbers"),
+        None => default,
+    }
+}
+
+/// answers every request on the connection with size bytes
+fn serve(mut stream: TcpStream, latency: Duration, size: usize) -> io::Result<()> {
+    stream.set_nodelay(true)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let header = format!("HTTP/1.1 200 OK\r\nContent-Length: {size}\r\n\r\n");
+    let body = vec![0; size];
+
+    loop {
+        // skips the request line and headers, the body is the same for every path
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            if line == "\r\n" {
+                break;
+            }
+        }
+
+        thread::sleep(latency);
+        stream.write_all(header.as_bytes())?;
+        stream.write_all(&body)?;
+    }
+}
+
+/// starts a keep-alive server on a free port with a thread per connection
+fn start_server(latency: Duration, size: usize) -> Url {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            thread::spawn(move || serve(stream, latency, size).ok());
+        }
+    });
+
+    url
+}
+
+fn main() {
+    let files = get_arg(1, 500);
+    let workers = get_arg(2, 8);
+    let latency = Duration::from_millis(get_arg(3, 20) as u64);
+    let size = get_arg(4, 64) * 1024;
+
+    let url = start_server(latency, size);
+    let dir = tempfile::tempdir().unwrap();
+    let launcher = Launcher::new(dir.path(), config::get_default_config()).unwrap();
+
+    let downloads: Vec<Download> = (0..files)
+        .map(|i| Download {
+            url: url.join(&i.to_string()).unwrap(),
+            path: dir.path().join("files").join(i.to_string()),
+            sha1: None,
+            size: Some(size as u64),
+        })
+        .collect();
+    let options = DownloadOptions {
+        workers,
+        ..DownloadOptions::default()
+    };
+
+    let start = Instant::now();
+    launcher
+        .download_all(&downloads, &options, &NoProgress)
+        .unwrap();
+    let elapsed = start.elapsed().as_secs_f64();
+
+    println!(
+        "{files} files of {} KiB with {workers} workers and {} ms latency: {elapsed:.2} s, {:.0} files/s, {:.1} MiB/s",
+        size / 1024,
+        latency.as_millis(),
+        files as f64 / elapsed,
+        (files * size) as f64 / elapsed / (1024.0 * 1024.0),
+    );
+}
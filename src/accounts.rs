@@ -2,17 +2,20 @@ use std::{
     collections::HashMap,
     fs::File,
     io::{BufReader, BufWriter},
-    path::{Path, PathBuf},
+    path::PathBuf,
 };
 
-use anyhow::Result;
+use chrono::{DateTime, Local};
 use serde::{Deserialize, Serialize};
+use tracing::{info, instrument};
 use url::Url;
 
 use crate::{
-    msa::{self, MsAccount},
-    profile::{get_minecraft_account, get_user_profile, McAccount},
-    BASE_DIR,
+    cancel::CancellationToken,
+    error::{Error, Result},
+    msa::MsAccount,
+    profile::{McAccount, UserProfile},
+    runtime, Launcher,
 };
 
 #[derive(Serialize, Deserialize)]
@@ -28,8 +31,27 @@ struct Config {
     accounts: HashMap<String, Account>,
 }
 
-lazy_static! {
-    static ref ACCOUNTS_PATH: PathBuf = BASE_DIR.join("accounts").with_extension("json");
+/// Credentials of a stored account, ready to launch the game
+#[derive(Clone)]
+pub struct Session {
+    pub profile: UserProfile,
+    pub access_token: String,
+    /// value of the user_type launch argument
+    pub user_type: String,
+}
+
+/// Pending device code sign-in, the user enters user_code at verification_uri
+#[derive(Clone)]
+pub struct DeviceCode {
+    pub user_code: String,
+    pub verification_uri: Url,
+    /// instructions to show to the user, localized by Microsoft
+    pub message: String,
+    /// the sign-in must be completed before this
+    pub expires: DateTime<Local>,
+    pub(crate) device_code: String,
+    /// seconds between polls
+    pub(crate) interval: u64,
 }
 
 fn get_new_config() -> Config {
@@ -39,69 +61,253 @@ fn get_new_config() -> Config {
     }
 }
 
-fn write(config: &Config) -> Result<()> {
-    let file = File::create(ACCOUNTS_PATH.as_path())?;
-    let writer = BufWriter::new(file);
+impl Launcher {
+    fn get_accounts_path(&self) -> PathBuf {
+        self.get_root_dir().join("accounts").with_extension("json")
+    }
 
-    serde_json::to_writer_pretty(writer, config)?;
+    fn write_accounts(&self, config: &Config) -> Result<()> {
+        let file = File::create(self.get_accounts_path())?;
+        let writer = BufWriter::new(file);
 
-    Ok(())
-}
+        serde_json::to_writer_pretty(writer, config)?;
 
-fn new() -> Result<Config> {
-    let config = get_new_config();
-    write(&config)?;
+        Ok(())
+    }
 
-    Ok(config)
-}
+    fn new_accounts(&self) -> Result<Config> {
+        let config = get_new_config();
+        self.write_accounts(&config)?;
 
-fn read() -> Result<Config> {
-    if !Path::is_file(ACCOUNTS_PATH.as_path()) {
-        return new();
+        Ok(config)
     }
 
-    let file = File::open(ACCOUNTS_PATH.as_path())?;
-    let reader = BufReader::new(file);
+    fn read_accounts(&self) -> Result<Config> {
+        let path = self.get_accounts_path();
+        if !path.is_file() {
+            return self.new_accounts();
+        }
 
-    let config = serde_json::from_reader(reader)?;
+        let file = File::open(path)?;
+        let reader = BufReader::new(file);
 
-    Ok(config)
-}
+        let config = serde_json::from_reader(reader)?;
 
-pub fn get_auth_url() -> Result<Url, url::ParseError> {
-    msa::get_auth_url()
-}
+        Ok(config)
+    }
 
-pub fn add() -> Result<()> {
-    let msa = msa::get_account()?;
+    pub fn get_auth_url(&self) -> Result<Url> {
+        self.get_ms_auth_url()
+    }
 
-    let mca = get_minecraft_account(&msa.access_token)?;
-    let profile = get_user_profile(&mca)?;
+    #[instrument(skip_all)]
+    pub(crate) async fn add_account_async(&self) -> Result<()> {
+        let msa = self.get_ms_account().await?;
 
-    let mut config = read()?;
-    let account = Account {
-        id: profile.id,
-        msa,
-        mca,
-    };
-    config.accounts.insert(profile.name, account);
-    write(&config)?;
+        self.store_account(msa).await
+    }
 
-    Ok(())
-}
+    pub fn add_account(&self) -> Result<()> {
+        runtime::block_on(self.add_account_async())
+    }
+
+    /// starts a sign-in without a local browser, show the returned code to the user
+    /// then call add_account_with_device_code
+    pub fn get_device_code(&self) -> Result<DeviceCode> {
+        runtime::block_on(self.request_device_code())
+    }
 
-pub fn remove(name: &str) -> Result<()> {
-    let mut config = read()?;
-    config.accounts.remove(name);
+    #[instrument(skip_all)]
+    pub(crate) async fn add_account_with_device_code_async(
+        &self,
+        device_code: &DeviceCode,
+        cancel: &CancellationToken,
+    ) -> Result<()> {
+        let msa = self
+            .get_ms_account_with_device_code(device_code, cancel)
+            .await?;
+
+        self.store_account(msa).await
+    }
+
+    /// waits until the user completes the device code sign-in and stores the account,
+    /// fails with AuthError::DeviceCodeExpired if it isn't completed in time
+    pub fn add_account_with_device_code(
+        &self,
+        device_code: &DeviceCode,
+        cancel: &CancellationToken,
+    ) -> Result<()> {
+        runtime::block_on(self.add_account_with_device_code_async(device_code, cancel))
+    }
+
+    async fn store_account(&self, msa: MsAccount) -> Result<()> {
+        let mca = self.get_minecraft_account_async(&msa.access_token).await?;
+        let profile = self.get_user_profile_async(&mca).await?;
+
+        let mut config = self.read_accounts()?;
+        let account = Account {
+            id: profile.id,
+            msa,
+            mca,
+        };
+        info!("added account {}", profile.name);
+        config.accounts.insert(profile.name, account);
+        self.write_accounts(&config)?;
+
+        Ok(())
+    }
+
+    pub fn remove_account(&self, name: &str) -> Result<()> {
+        let mut config = self.read_accounts()?;
+        config.accounts.remove(name);
+        info!("removed account {}", name);
+
+        self.write_accounts(&config)?;
+
+        Ok(())
+    }
+
+    async fn refresh_tokens(&self, account: &mut Account) -> Result<()> {
+        let msa = self.refresh_ms_account(&account.msa).await?;
+        let mca = self.get_minecraft_account_async(&msa.access_token).await?;
+        account.msa = msa;
+        account.mca = mca;
+
+        Ok(())
+    }
 
-    write(&config)?;
+    #[instrument(skip_all, fields(name = %name))]
+    pub(crate) async fn refresh_account_async(&self, name: &str) -> Result<()> {
+        let mut config = self.read_accounts()?;
+        let account = config
+            .accounts
+            .get_mut(name)
+            .ok_or_else(|| Error::AccountNotFound(name.to_string()))?;
 
-    Ok(())
+        self.refresh_tokens(account).await?;
+        self.write_accounts(&config)?;
+        info!("refreshed account {}", name);
+
+        Ok(())
+    }
+
+    /// renews the Microsoft and Minecraft tokens of the account and saves them
+    pub fn refresh_account(&self, name: &str) -> Result<()> {
+        runtime::block_on(self.refresh_account_async(name))
+    }
+
+    #[instrument(skip_all, fields(name = %name))]
+    pub(crate) async fn get_session_async(&self, name: &str) -> Result<Session> {
+        let mut config = self.read_accounts()?;
+        let account = config
+            .accounts
+            .get_mut(name)
+            .ok_or_else(|| Error::AccountNotFound(name.to_string()))?;
+
+        if account.mca.is_expired() {
+            self.refresh_tokens(account).await?;
+            self.write_accounts(&config)?;
+            info!("refreshed account {}", name);
+        }
+
+        let account = &config.accounts[name];
+        let session = Session {
+            profile: UserProfile {
+                id: account.id.clone(),
+                name: name.to_string(),
+            },
+            access_token: account.mca.access_token.clone(),
+            user_type: "msa".to_string(),
+        };
+
+        Ok(session)
+    }
+
+    /// returns the session of the account, refreshing its tokens first when expired
+    pub fn get_session(&self, name: &str) -> Result<Session> {
+        runtime::block_on(self.get_session_async(name))
+    }
+
+    pub fn list_accounts(&self) -> Result<Vec<String>> {
+        let config = self.read_accounts()?;
+        let accounts = config.accounts.into_keys().collect();
+
+        Ok(accounts)
+    }
 }
 
-pub fn list() -> Result<Vec<String>> {
-    let config = read()?;
-    let accounts = config.accounts.into_keys().collect();
+#[cfg(test)]
+pub(crate) mod tests {
+    use chrono::Duration;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{launcher::tests::get_launcher, msa, profile};
 
-    Ok(accounts)
+    pub(crate) fn write_account(launcher: &Launcher, expires: DateTime<Local>) {
+        let config = json!({
+            "format_version": "0",
+            "accounts": {
+                "Steve": {
+                    "id": "uuid",
+                    "msa": {
+                        "access_token": "old-ms-token",
+                        "token_type": "Bearer",
+                        "expires": expires,
+                        "refresh_token": "refresh-token"
+                    },
+                    "mca": {
+                        "access_token": "old-mc-token",
+                        "token_type": "Bearer",
+                        "expires": expires
+                    }
+                }
+            }
+        });
+
+        std::fs::write(launcher.get_accounts_path(), config.to_string()).unwrap();
+    }
+
+    #[test]
+    fn session_of_valid_account() {
+        let (_dir, launcher, transport) = get_launcher();
+        write_account(&launcher, Local::now() + Duration::hours(1));
+
+        let session = launcher.get_session("Steve").unwrap();
+
+        assert_eq!(session.access_token, "old-mc-token");
+        assert_eq!(session.profile.id, "uuid");
+        assert_eq!(session.profile.name, "Steve");
+        assert!(transport.get_requests().is_empty());
+    }
+
+    #[test]
+    fn session_refreshes_expired_token() {
+        let (_dir, launcher, transport) = get_launcher();
+        write_account(&launcher, Local::now() - Duration::hours(1));
+        msa::tests::respond_token(
+            &transport,
+            200,
+            msa::tests::get_token_response("new-ms-token"),
+        );
+        profile::tests::respond_sign_in(&transport, "new-mc-token");
+
+        let session = launcher.get_session("Steve").unwrap();
+        assert_eq!(session.access_token, "new-mc-token");
+
+        // the refreshed tokens were saved
+        let requests = transport.get_requests().len();
+        let session = launcher.get_session("Steve").unwrap();
+        assert_eq!(session.access_token, "new-mc-token");
+        assert_eq!(transport.get_requests().len(), requests);
+    }
+
+    #[test]
+    fn missing_account() {
+        let (_dir, launcher, _) = get_launcher();
+
+        let result = launcher.get_session("Alex");
+
+        assert!(matches!(result, Err(Error::AccountNotFound(name)) if name == "Alex"));
+    }
 }
@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+
+use crate::error::{Error, Result};
+
+/// Placeholders that can appear as ${name} inside launch arguments
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Variable {
+    AuthPlayerName,
+    AuthUuid,
+    AuthAccessToken,
+    AuthSession,
+    AuthXuid,
+    UserType,
+    UserProperties,
+    ClientId,
+    VersionName,
+    VersionType,
+    GameDirectory,
+    AssetsRoot,
+    AssetsIndexName,
+    GameAssets,
+    LibraryDirectory,
+    NativesDirectory,
+    Classpath,
+    ClasspathSeparator,
+    LauncherName,
+    LauncherVersion,
+    ResolutionWidth,
+    ResolutionHeight,
+    QuickPlayPath,
+    QuickPlaySingleplayer,
+    QuickPlayMultiplayer,
+    QuickPlayRealms,
+}
+
+impl Variable {
+    pub fn from_name(name: &str) -> Option<Self> {
+        let variable = match name {
+            "auth_player_name" => Self::AuthPlayerName,
+            "auth_uuid" => Self::AuthUuid,
+            "auth_access_token" => Self::AuthAccessToken,
+            "auth_session" => Self::AuthSession,
+            "auth_xuid" => Self::AuthXuid,
+            "user_type" => Self::UserType,
+            "user_properties" => Self::UserProperties,
+            "clientid" => Self::ClientId,
+            "version_name" => Self::VersionName,
+            "version_type" => Self::VersionType,
+            "game_directory" => Self::GameDirectory,
+            "assets_root" => Self::AssetsRoot,
+            "assets_index_name" => Self::AssetsIndexName,
+            "game_assets" => Self::GameAssets,
+            "library_directory" => Self::LibraryDirectory,
+            "natives_directory" => Self::NativesDirectory,
+            "classpath" => Self::Classpath,
+            "classpath_separator" => Self::ClasspathSeparator,
+            "launcher_name" => Self::LauncherName,
+            "launcher_version" => Self::LauncherVersion,
+            "resolution_width" => Self::ResolutionWidth,
+            "resolution_height" => Self::ResolutionHeight,
+            "quickPlayPath" => Self::QuickPlayPath,
+            "quickPlaySingleplayer" => Self::QuickPlaySingleplayer,
+            "quickPlayMultiplayer" => Self::QuickPlayMultiplayer,
+            "quickPlayRealms" => Self::QuickPlayRealms,
+            _ => return None,
+        };
+
+        Some(variable)
+    }
+}
+
+#[derive(Default)]
+pub struct Variables {
+    values: HashMap<Variable, String>,
+}
+
+impl Variables {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&mut self, variable: Variable, value: impl Into<String>) -> &mut Self {
+        self.values.insert(variable, value.into());
+        self
+    }
+
+    pub fn get(&self, variable: Variable) -> Option<&str> {
+        self.values.get(&variable).map(String::as_str)
+    }
+
+    fn resolve(&self, name: &str) -> Option<&str> {
+        Variable::from_name(name).and_then(|variable| self.get(variable))
+    }
+
+    /// replaces every ${name} token inside arg, pushing unresolved names to missing
+    fn substitute_arg(&self, arg: &str, missing: &mut Vec<String>) -> String {
+        let mut result = String::with_capacity(arg.len());
+        let mut rest = arg;
+
+        while let Some(start) = rest.find("${") {
+            let Some(len) = rest[start + 2..].find('}') else {
+                break;
+            };
+
+            let name = &rest[start + 2..start + 2 + len];
+            result.push_str(&rest[..start]);
+
+            match self.resolve(name) {
+                Some(value) => result.push_str(value),
+                None => {
+                    if !missing.iter().any(|m| m == name) {
+                        missing.push(name.to_string());
+                    }
+                    result.push_str(&rest[start..start + 3 + len]);
+                }
+            }
+
+            rest = &rest[start + 3 + len..];
+        }
+
+        result.push_str(rest);
+
+        result
+    }
+
+    /// Replaces every ${name} token found anywhere in the arguments.
+    /// Fails listing all the variables that could not be resolved.
+    pub fn substitute(&self, args: &[String]) -> Result<Vec<String>> {
+        let mut missing = Vec::new();
+
+        let args = args
+            .iter()
+            .map(|arg| self.substitute_arg(arg, &mut missing))
+            .collect();
+
+        if !missing.is_empty() {
+            return Err(Error::UnresolvedVariables(missing));
+        }
+
+        Ok(args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn get_variables() -> Variables {
+        let mut variables = Variables::new();
+        variables
+            .set(Variable::AuthPlayerName, "Steve")
+            .set(Variable::NativesDirectory, "/natives")
+            .set(Variable::LauncherName, "launcher")
+            .set(Variable::LauncherVersion, "1.0");
+
+        variables
+    }
+
+    #[test]
+    fn whole_arg() {
+        let args = get_variables()
+            .substitute(&get_args(&["--username", "${auth_player_name}"]))
+            .unwrap();
+
+        assert_eq!(args, ["--username", "Steve"]);
+    }
+
+    #[test]
+    fn embedded_token() {
+        let args = get_variables()
+            .substitute(&get_args(&["-Djava.library.path=${natives_directory}"]))
+            .unwrap();
+
+        assert_eq!(args, ["-Djava.library.path=/natives"]);
+    }
+
+    #[test]
+    fn several_tokens() {
+        let args = get_variables()
+            .substitute(&get_args(&["${launcher_name}-${launcher_version}!"]))
+            .unwrap();
+
+        assert_eq!(args, ["launcher-1.0!"]);
+    }
+
+    #[test]
+    fn unterminated_token_is_kept() {
+        let args = get_variables()
+            .substitute(&get_args(&["${auth_player_name} ${natives_directory"]))
+            .unwrap();
+
+        assert_eq!(args, ["Steve ${natives_directory"]);
+    }
+
+    #[test]
+    fn unresolved_variables_are_listed() {
+        let result = get_variables().substitute(&get_args(&[
+            "${auth_uuid}",
+            "${unknown}-${auth_player_name}",
+            "--uuid=${auth_uuid}",
+        ]));
+
+        match result {
+            Err(Error::UnresolvedVariables(missing)) => {
+                assert_eq!(missing, ["auth_uuid", "unknown"])
+            }
+            _ => panic!("expected unresolved variables"),
+        }
+    }
+}
@@ -1,65 +1,181 @@
-use crate::{download_file, launchermeta::AssetIndexMeta, BASE_DIR};
-use std::{fs::File, io::BufReader, path::PathBuf};
+use crate::{
+    cancel::CancellationToken,
+    download::{Download, DownloadOptions},
+    error::Result,
+    launchermeta::AssetIndexMeta,
+    progress::Progress,
+    runtime, Launcher,
+};
+use std::{
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
-use anyhow::Result;
 use serde::Deserialize;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use url::Url;
 
-const RESOURCES_URL: &str = "https://resources.download.minecraft.net/";
-
 #[derive(Deserialize)]
 pub struct AssetIndex {
     objects: HashMap<String, Object>,
+    /// set by the "legacy" index, objects are expected in virtual/<id>
+    #[serde(default)]
+    r#virtual: bool,
+    /// set by the "pre-1.6" index, objects are expected in <game_dir>/resources
+    #[serde(default)]
+    map_to_resources: bool,
 }
 
 #[derive(Deserialize, Clone)]
 struct Object {
+    /// sha1 of the object
     hash: String,
+    size: Option<u64>,
 }
 
-lazy_static! {
-    pub static ref ASSETS_DIR: PathBuf = BASE_DIR.join("assets");
-    static ref OBJECTS_DIR: PathBuf = ASSETS_DIR.join("objects");
-    static ref INDEXES_DIR: PathBuf = ASSETS_DIR.join("indexes");
-}
+impl Launcher {
+    pub fn get_assets_dir(&self) -> PathBuf {
+        self.get_root_dir().join("assets")
+    }
 
-fn download_asset(hash: &str) -> Result<()> {
-    let first2 = &hash[..2];
+    fn get_object_path(&self, hash: &str) -> PathBuf {
+        self.get_assets_dir()
+            .join("objects")
+            .join(&hash[..2])
+            .join(hash)
+    }
 
-    let path = OBJECTS_DIR.join(&first2).join(&hash);
-    let url = Url::parse(RESOURCES_URL)?.join(first2)?.join(hash)?;
+    fn get_asset_index_path(&self, id: &str) -> PathBuf {
+        self.get_assets_dir()
+            .join("indexes")
+            .join(format!("{id}.json"))
+    }
 
-    download_file(&url, &path)?;
+    fn get_asset_download(&self, resources_url: &Url, object: &Object) -> Result<Download> {
+        let hash = &object.hash;
+        let first2 = &hash[..2];
 
-    Ok(())
-}
+        let path = self.get_object_path(hash);
+        let url = resources_url.join(&format!("{first2}/{hash}"))?;
 
-fn download_asset_index(asset_index_meta: &AssetIndexMeta) -> Result<()> {
-    let path = INDEXES_DIR.join(&asset_index_meta.id).with_extension("json");
-    download_file(&asset_index_meta.url, &path)?;
+        Ok(Download {
+            url,
+            path,
+            sha1: Some(hash.clone()),
+            size: object.size,
+        })
+    }
 
-    Ok(())
-}
+    async fn download_asset_index(
+        &self,
+        asset_index_meta: &AssetIndexMeta,
+        options: &DownloadOptions,
+        progress: &dyn Progress,
+    ) -> Result<()> {
+        // a batch of its own, the objects are only known once it is read
+        let download = Download {
+            url: asset_index_meta.url.clone(),
+            path: self.get_asset_index_path(&asset_index_meta.id),
+            sha1: asset_index_meta.sha1.clone(),
+            size: asset_index_meta.size,
+        };
+
+        self.download_all_async(&[download], options, progress)
+            .await
+    }
 
-fn read_asset_index(id: &str) -> Result<AssetIndex> {
-    let path = INDEXES_DIR.join(id).with_extension("json");
+    fn read_asset_index(&self, id: &str) -> Result<AssetIndex> {
+        let path = self.get_asset_index_path(id);
 
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
+        let file = File::open(path)?;
+        let reader = BufReader::new(file);
 
-    let asset_index: AssetIndex = serde_json::from_reader(reader)?;
+        let asset_index: AssetIndex = serde_json::from_reader(reader)?;
 
-    Ok(asset_index)
-}
+        Ok(asset_index)
+    }
 
-pub fn download_assets(asset_index_meta: &AssetIndexMeta) -> Result<()> {
-    download_asset_index(asset_index_meta)?;
-    let asset_index = read_asset_index(&asset_index_meta.id)?;
+    /// copies the objects to their named paths, as pre-1.7 versions expect
+    async fn copy_to_named_paths(&self, asset_index: &Arc<AssetIndex>, dir: PathBuf) -> Result<()> {
+        let launcher = self.clone();
+        let asset_index = asset_index.clone();
+
+        runtime::spawn_blocking(move || {
+            for (name, object) in &asset_index.objects {
+                let path = dir.join(name);
+                if path.is_file() {
+                    continue;
+                }
+
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::copy(launcher.get_object_path(&object.hash), path)?;
+            }
+
+            Ok(())
+        })
+        .await
+    }
 
-    for (_, object) in asset_index.objects.into_iter() {
-        download_asset(&object.hash)?;
+    pub(crate) async fn download_assets_async(
+        &self,
+        asset_index_meta: &AssetIndexMeta,
+        game_dir: &Path,
+        progress: &dyn Progress,
+        cancel: &CancellationToken,
+    ) -> Result<PathBuf> {
+        let resources_url = self.get_config().endpoints.resources;
+        let options = self.get_download_options(cancel);
+
+        self.download_asset_index(asset_index_meta, &options, progress)
+            .await?;
+        let asset_index = Arc::new(self.read_asset_index(&asset_index_meta.id)?);
+
+        // several names can share the same object, sorting by hash dedups them
+        // and keeps the download order stable
+        let objects: BTreeMap<&str, &Object> = asset_index
+            .objects
+            .values()
+            .map(|object| (object.hash.as_str(), object))
+            .collect();
+        let downloads = objects
+            .into_values()
+            .map(|object| self.get_asset_download(&resources_url, object))
+            .collect::<Result<Vec<Download>>>()?;
+
+        self.download_all_async(&downloads, &options, progress)
+            .await?;
+
+        if asset_index.map_to_resources {
+            self.copy_to_named_paths(&asset_index, game_dir.join("resources"))
+                .await?;
+        }
+
+        if asset_index.r#virtual || asset_index.map_to_resources {
+            let virtual_dir = self
+                .get_assets_dir()
+                .join("virtual")
+                .join(&asset_index_meta.id);
+            self.copy_to_named_paths(&asset_index, virtual_dir.clone())
+                .await?;
+
+            return Ok(virtual_dir);
+        }
+
+        Ok(self.get_assets_dir())
     }
 
-    Ok(())
+    /// returns the directory to pass as ${game_assets}
+    pub fn download_assets(
+        &self,
+        asset_index_meta: &AssetIndexMeta,
+        game_dir: &Path,
+        progress: &dyn Progress,
+        cancel: &CancellationToken,
+    ) -> Result<PathBuf> {
+        runtime::block_on(self.download_assets_async(asset_index_meta, game_dir, progress, cancel))
+    }
 }
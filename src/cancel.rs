@@ -0,0 +1,59 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    error::{Error, Result},
+    runtime,
+};
+
+/// Shared flag to stop downloads and launch preparation from another thread
+#[derive(Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// returns Error::Cancelled if the token was tripped
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+
+        Ok(())
+    }
+
+    pub(crate) async fn sleep_async(&self, duration: Duration) -> Result<()> {
+        const STEP: Duration = Duration::from_millis(50);
+
+        let deadline = Instant::now() + duration;
+        loop {
+            self.check()?;
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(());
+            }
+            runtime::sleep(STEP.min(deadline - now)).await;
+        }
+    }
+
+    /// sleeps for duration, waking up early if the token is tripped
+    pub fn sleep(&self, duration: Duration) -> Result<()> {
+        runtime::block_on(self.sleep_async(duration))
+    }
+}
@@ -1,29 +1,55 @@
 use std::{
     fs::File,
     io::{BufReader, BufWriter},
-    path::{Path, PathBuf},
+    path::Path,
 };
 
-use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
-use crate::BASE_DIR;
+use crate::{
+    download::{self, Mirror},
+    endpoints::Endpoints,
+    error::Result,
+    http::HttpConfig,
+};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct JavaConfig {
     pub path: String,
     pub memory: String,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Config {
     pub locale: String,
     pub java: JavaConfig,
     pub last_launched_instance: String,
+    /// number of files downloaded at the same time
+    #[serde(default = "get_default_download_workers")]
+    pub download_workers: usize,
+    /// retries of a failed download before moving to the next mirror
+    #[serde(default = "get_default_download_retries")]
+    pub download_retries: u32,
+    #[serde(default = "get_default_download_backoff_ms")]
+    pub download_backoff_ms: u64,
+    #[serde(default)]
+    pub mirrors: Vec<Mirror>,
+    #[serde(default)]
+    pub http: HttpConfig,
+    #[serde(default)]
+    pub endpoints: Endpoints,
+}
+
+fn get_default_download_workers() -> usize {
+    download::DEFAULT_WORKERS
+}
+
+fn get_default_download_retries() -> u32 {
+    download::DEFAULT_RETRIES
 }
 
-lazy_static! {
-    pub static ref CONFIG_PATH: PathBuf = BASE_DIR.join("config").with_extension("json");
+fn get_default_download_backoff_ms() -> u64 {
+    download::DEFAULT_BACKOFF_MS
 }
 
 pub fn get_default_config() -> Config {
@@ -34,11 +60,17 @@ pub fn get_default_config() -> Config {
             memory: "2G".to_string(),
         },
         last_launched_instance: String::new(),
+        download_workers: get_default_download_workers(),
+        download_retries: get_default_download_retries(),
+        download_backoff_ms: get_default_download_backoff_ms(),
+        mirrors: Vec::new(),
+        http: HttpConfig::default(),
+        endpoints: Endpoints::default(),
     }
 }
 
-pub fn write(config: &Config) -> Result<()> {
-    let file = File::create(CONFIG_PATH.as_path())?;
+pub fn write(path: &Path, config: &Config) -> Result<()> {
+    let file = File::create(path)?;
     let writer = BufWriter::new(file);
 
     serde_json::to_writer_pretty(writer, config)?;
@@ -46,19 +78,19 @@ pub fn write(config: &Config) -> Result<()> {
     Ok(())
 }
 
-pub fn new() -> Result<Config> {
+pub fn new(path: &Path) -> Result<Config> {
     let config = get_default_config();
-    write(&config)?;
+    write(path, &config)?;
 
     Ok(config)
 }
 
-pub fn read() -> Result<Config> {
-    if !Path::is_file(CONFIG_PATH.as_path()) {
-        return new();
+pub fn read(path: &Path) -> Result<Config> {
+    if !path.is_file() {
+        return new(path);
     }
 
-    let file = File::open(CONFIG_PATH.as_path())?;
+    let file = File::open(path)?;
     let reader = BufReader::new(file);
 
     let config = serde_json::from_reader(reader)?;
@@ -0,0 +1,1059 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+#[cfg(feature = "tokio")]
+use std::{panic, sync::Arc};
+#[cfg(not(feature = "tokio"))]
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+#[cfg(feature = "tokio")]
+use tokio::{
+    sync::{mpsc, Semaphore},
+    task::JoinSet,
+};
+#[cfg(feature = "tokio")]
+use tracing::Instrument;
+use tracing::{debug, warn, Span};
+use url::Url;
+
+use crate::{
+    cancel::CancellationToken,
+    config::Config,
+    error::{format_chain, Error, Result},
+    http::{HttpRequest, HttpResponse},
+    progress::{Event, Progress},
+    runtime, Launcher,
+};
+
+pub const DEFAULT_WORKERS: usize = 8;
+pub const DEFAULT_RETRIES: u32 = 3;
+pub const DEFAULT_BACKOFF_MS: u64 = 500;
+
+/// replaces the `prefix` of url with `replacement`, None when url doesn't start with it
+pub(crate) fn replace_prefix(url: &Url, prefix: &str, replacement: &Url) -> Option<Url> {
+    let rest = url.as_str().strip_prefix(prefix)?;
+
+    replacement.join(rest).ok()
+}
+
+/// returns the lowercase hex sha1 of the file at path
+fn get_sha1(path: &Path) -> Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha1::new();
+    io::copy(&mut file, &mut hasher)?;
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// checks the file against the expected size and sha1, when known
+pub fn verify_file(path: &Path, sha1: Option<&str>, size: Option<u64>) -> Result<bool> {
+    if let Some(size) = size {
+        if fs::metadata(path)?.len() != size {
+            return Ok(false);
+        }
+    }
+
+    if let Some(sha1) = sha1 {
+        if !get_sha1(path)?.eq_ignore_ascii_case(sha1) {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+/// verify_file on the blocking pool, hashing a big file takes a while
+pub(crate) async fn verify_file_async(
+    path: &Path,
+    sha1: Option<&str>,
+    size: Option<u64>,
+) -> Result<bool> {
+    let path = path.to_path_buf();
+    let sha1 = sha1.map(String::from);
+
+    runtime::spawn_blocking(move || verify_file(&path, sha1.as_deref(), size)).await
+}
+
+/// copies the response body into file, reporting every chunk as transferred bytes
+async fn copy_with_progress(
+    url: &Url,
+    resp: &mut HttpResponse,
+    file: File,
+    progress: &dyn Progress,
+    cancel: &CancellationToken,
+) -> Result<()> {
+    let mut writer = BufWriter::new(file);
+
+    loop {
+        cancel.check()?;
+
+        let Some(chunk) = resp.body.next_chunk().await? else {
+            break;
+        };
+        let bytes = chunk.len() as u64;
+
+        // the writer moves to the blocking pool and back for every chunk
+        writer = runtime::spawn_blocking(move || -> Result<_> {
+            writer.write_all(&chunk)?;
+            Ok(writer)
+        })
+        .await?;
+        progress.report(Event::BytesTransferred { url, bytes });
+    }
+
+    runtime::spawn_blocking(move || writer.flush()).await?;
+
+    Ok(())
+}
+
+/// returns the temporary sibling file used while downloading path
+fn get_part_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".part");
+
+    path.with_file_name(file_name)
+}
+
+/// client errors won't go away by asking again, everything else
+/// (timeouts, connection resets, 5xx, bad checksums) might
+fn is_retriable(error: &Error) -> bool {
+    match error {
+        Error::Cancelled => false,
+        Error::Status { status, .. } => *status >= 500 || *status == 408 || *status == 429,
+        _ => true,
+    }
+}
+
+/// Alternative host for every url starting with `url`,
+/// e.g. https://resources.download.minecraft.net/ -> https://bmclapi2.bangbang93.com/assets/
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Mirror {
+    pub url: Url,
+    pub mirror_url: Url,
+}
+
+#[derive(Clone)]
+pub struct DownloadOptions {
+    /// number of files downloaded at the same time by download_all
+    pub workers: usize,
+    /// attempts per url after the first one, for timeouts and 5xx responses
+    pub retries: u32,
+    /// delay before the first retry, doubled at every attempt
+    pub initial_backoff: Duration,
+    /// tried in order after the original url
+    pub mirrors: Vec<Mirror>,
+    pub cancel: CancellationToken,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        Self {
+            workers: DEFAULT_WORKERS,
+            retries: DEFAULT_RETRIES,
+            initial_backoff: Duration::from_millis(DEFAULT_BACKOFF_MS),
+            mirrors: Vec::new(),
+            cancel: CancellationToken::new(),
+        }
+    }
+}
+
+impl DownloadOptions {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            workers: config.download_workers,
+            retries: config.download_retries,
+            initial_backoff: Duration::from_millis(config.download_backoff_ms),
+            mirrors: config.mirrors.clone(),
+            cancel: CancellationToken::new(),
+        }
+    }
+
+    pub fn with_cancel(mut self, cancel: &CancellationToken) -> Self {
+        self.cancel = cancel.clone();
+        self
+    }
+
+    /// returns url followed by its mirrored versions
+    pub(crate) fn get_candidates(&self, url: &Url) -> Vec<Url> {
+        let mut candidates = vec![url.clone()];
+
+        for mirror in &self.mirrors {
+            if let Some(candidate) = replace_prefix(url, mirror.url.as_str(), &mirror.mirror_url) {
+                candidates.push(candidate);
+            }
+        }
+
+        candidates
+    }
+
+    /// returns the delay before the given retry (starting from 1)
+    pub(crate) fn get_backoff(&self, attempt: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+    }
+}
+
+/// A file to fetch, with the checksum published for it
+#[derive(Clone)]
+pub struct Download {
+    pub url: Url,
+    pub path: PathBuf,
+    pub sha1: Option<String>,
+    pub size: Option<u64>,
+}
+
+impl Download {
+    async fn run(
+        &self,
+        launcher: &Launcher,
+        options: &DownloadOptions,
+        progress: &dyn Progress,
+    ) -> Result<()> {
+        launcher
+            .download_file_async(
+                &self.url,
+                &self.path,
+                self.sha1.as_deref(),
+                self.size,
+                options,
+                progress,
+            )
+            .await
+    }
+}
+
+/// Event of a download task, sent to the task running download_all
+#[cfg(feature = "tokio")]
+enum Message {
+    FileStarted {
+        url: Url,
+        path: PathBuf,
+    },
+    BytesTransferred {
+        url: Url,
+        bytes: u64,
+    },
+    FileFinished {
+        url: Url,
+        path: PathBuf,
+        reused: bool,
+    },
+    Retrying {
+        url: Url,
+        attempt: u32,
+    },
+    /// index of the download and its error
+    Failed(usize, Error),
+}
+
+/// Progress of a download task, the failures are sent with the returned error instead
+#[cfg(feature = "tokio")]
+struct ChannelProgress(mpsc::UnboundedSender<Message>);
+
+#[cfg(feature = "tokio")]
+impl Progress for ChannelProgress {
+    fn report(&self, event: Event) {
+        let message = match event {
+            Event::FileStarted { url, path } => Message::FileStarted {
+                url: url.clone(),
+                path: path.to_path_buf(),
+            },
+            Event::BytesTransferred { url, bytes } => Message::BytesTransferred {
+                url: url.clone(),
+                bytes,
+            },
+            Event::FileFinished { url, path, reused } => Message::FileFinished {
+                url: url.clone(),
+                path: path.to_path_buf(),
+                reused,
+            },
+            Event::Retrying { url, attempt } => Message::Retrying {
+                url: url.clone(),
+                attempt,
+            },
+            _ => return,
+        };
+
+        // the receiver only goes away when download_all is dropped
+        self.0.send(message).ok();
+    }
+}
+
+impl Launcher {
+    /// returns the download settings of the launcher config
+    pub fn get_download_options(&self, cancel: &CancellationToken) -> DownloadOptions {
+        DownloadOptions::from_config(&self.get_config()).with_cancel(cancel)
+    }
+
+    /// Downloads url into part_path, appending to it if a previous download was interrupted
+    /// and the server accepts range requests. Returns true if the download was resumed.
+    async fn download_part(
+        &self,
+        url: &Url,
+        part_path: &Path,
+        progress: &dyn Progress,
+        cancel: &CancellationToken,
+    ) -> Result<bool> {
+        let mut offset = match fs::metadata(part_path) {
+            Ok(metadata) => metadata.len(),
+            Err(_) => 0,
+        };
+
+        let mut resp = loop {
+            let mut request = HttpRequest::get(url);
+            if offset > 0 {
+                request = request.header("Range", &format!("bytes={offset}-"));
+            }
+
+            match self.send_request(request).await {
+                // the part file is already complete (or bigger than the remote file)
+                Err(Error::Status { status: 416, .. }) if offset > 0 => {
+                    debug!(
+                        "{:?} is not a prefix of {}, downloading it again",
+                        part_path, url
+                    );
+                    fs::remove_file(part_path)?;
+                    offset = 0;
+                }
+                resp => break resp?,
+            }
+        };
+
+        let resumed = offset > 0
+            && resp.status == 206
+            && resp
+                .header("Content-Range")
+                .is_some_and(|range| range.starts_with(&format!("bytes {offset}-")));
+
+        if resumed {
+            debug!("resuming {} from byte {}", url, offset);
+            // the progress of the file includes what was downloaded before
+            progress.report(Event::BytesTransferred { url, bytes: offset });
+        }
+
+        let file = match resumed {
+            true => OpenOptions::new().append(true).open(part_path)?,
+            false => File::create(part_path)?,
+        };
+
+        copy_with_progress(url, &mut resp, file, progress, cancel).await?;
+
+        Ok(resumed)
+    }
+
+    /// downloads url into part_path and verifies it
+    async fn fetch_part(
+        &self,
+        url: &Url,
+        part_path: &Path,
+        sha1: Option<&str>,
+        size: Option<u64>,
+        progress: &dyn Progress,
+        cancel: &CancellationToken,
+    ) -> Result<()> {
+        let mut resumed = self.download_part(url, part_path, progress, cancel).await?;
+
+        while !verify_file_async(part_path, sha1, size).await? {
+            fs::remove_file(part_path)?;
+
+            // the partial data we resumed from may have been bad, start over once
+            if !resumed {
+                return Err(Error::ChecksumMismatch { url: url.clone() });
+            }
+            warn!("resumed download of {} is corrupted, starting over", url);
+            resumed = self.download_part(url, part_path, progress, cancel).await?;
+        }
+
+        Ok(())
+    }
+
+    /// returns true if a valid file was already present
+    async fn fetch_file(
+        &self,
+        url: &Url,
+        path: &Path,
+        sha1: Option<&str>,
+        size: Option<u64>,
+        options: &DownloadOptions,
+        progress: &dyn Progress,
+    ) -> Result<bool> {
+        options.cancel.check()?;
+
+        if path.exists() {
+            if verify_file_async(path, sha1, size).await? {
+                debug!("{:?} already present", path);
+                return Ok(true);
+            }
+
+            warn!("{:?} is corrupted, downloading it again", path);
+            fs::remove_file(path)?;
+        }
+
+        let dir = path
+            .parent()
+            .ok_or_else(|| Error::InvalidPath(path.to_path_buf()))?;
+        fs::create_dir_all(dir)?;
+
+        // the file is only moved into place once it's complete and verified,
+        // so an interrupted download never leaves a partial file at path
+        let part_path = get_part_path(path);
+        let mut last_error = None;
+
+        for candidate in options.get_candidates(url) {
+            for attempt in 0..=options.retries {
+                // a cancelled backoff goes through the same cleanup as a cancelled download
+                let result = async {
+                    if attempt > 0 {
+                        progress.report(Event::Retrying {
+                            url: &candidate,
+                            attempt,
+                        });
+                        options
+                            .cancel
+                            .sleep_async(options.get_backoff(attempt))
+                            .await?;
+                    }
+
+                    self.fetch_part(
+                        &candidate,
+                        &part_path,
+                        sha1,
+                        size,
+                        progress,
+                        &options.cancel,
+                    )
+                    .await
+                };
+
+                match result.await {
+                    Ok(()) => {
+                        fs::rename(&part_path, path)?;
+                        debug!("downloaded {} to {:?}", candidate, path);
+                        return Ok(false);
+                    }
+                    Err(Error::Cancelled) => {
+                        fs::remove_file(&part_path).ok();
+                        return Err(Error::Cancelled);
+                    }
+                    Err(e) => {
+                        warn!("error downloading {}: {}", candidate, format_chain(&e));
+                        let retriable = is_retriable(&e);
+                        last_error = Some(e);
+
+                        if !retriable {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        Err(last_error.expect("get_candidates returns at least url"))
+    }
+
+    pub(crate) async fn download_file_async(
+        &self,
+        url: &Url,
+        path: &Path,
+        sha1: Option<&str>,
+        size: Option<u64>,
+        options: &DownloadOptions,
+        progress: &dyn Progress,
+    ) -> Result<()> {
+        progress.report(Event::FileStarted { url, path });
+
+        match self
+            .fetch_file(url, path, sha1, size, options, progress)
+            .await
+        {
+            Ok(reused) => {
+                progress.report(Event::FileFinished { url, path, reused });
+                Ok(())
+            }
+            Err(error) => {
+                progress.report(Event::FileFailed { url, error: &error });
+                Err(error)
+            }
+        }
+    }
+
+    /// Downloads url to path, verifying it against sha1 and size when given.
+    /// An existing file is reused only if it passes the same checks.
+    /// Transient failures are retried with exponential backoff, then the mirrors are tried.
+    pub fn download_file(
+        &self,
+        url: &Url,
+        path: &Path,
+        sha1: Option<&str>,
+        size: Option<u64>,
+        options: &DownloadOptions,
+        progress: &dyn Progress,
+    ) -> Result<()> {
+        runtime::block_on(self.download_file_async(url, path, sha1, size, options, progress))
+    }
+
+    /// Runs every download as a task, at most `workers` of them at the same time.
+    /// Events are reported from the calling task, dropping the future aborts the downloads.
+    #[cfg(feature = "tokio")]
+    async fn run_downloads(
+        &self,
+        downloads: &[Download],
+        workers: usize,
+        options: &DownloadOptions,
+        progress: &dyn Progress,
+    ) -> Vec<(usize, Error)> {
+        let semaphore = Arc::new(Semaphore::new(workers));
+        let options = Arc::new(options.clone());
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let span = Span::current();
+
+        let mut tasks = JoinSet::new();
+        for (i, download) in downloads.iter().enumerate() {
+            let launcher = self.clone();
+            let download = download.clone();
+            let options = options.clone();
+            let semaphore = semaphore.clone();
+            let progress = ChannelProgress(sender.clone());
+
+            let task = async move {
+                let _permit = semaphore
+                    .acquire()
+                    .await
+                    .expect("semaphore is never closed");
+                if options.cancel.is_cancelled() {
+                    return;
+                }
+
+                if let Err(e) = download.run(&launcher, &options, &progress).await {
+                    progress.0.send(Message::Failed(i, e)).ok();
+                }
+            };
+            tasks.spawn(task.instrument(span.clone()));
+        }
+        drop(sender);
+
+        let mut errors = Vec::new();
+        // ends once every task is done and has dropped its sender
+        while let Some(message) = receiver.recv().await {
+            match message {
+                Message::FileStarted { url, path } => progress.report(Event::FileStarted {
+                    url: &url,
+                    path: &path,
+                }),
+                Message::BytesTransferred { url, bytes } => {
+                    progress.report(Event::BytesTransferred { url: &url, bytes })
+                }
+                Message::FileFinished { url, path, reused } => {
+                    progress.report(Event::FileFinished {
+                        url: &url,
+                        path: &path,
+                        reused,
+                    })
+                }
+                Message::Retrying { url, attempt } => {
+                    progress.report(Event::Retrying { url: &url, attempt })
+                }
+                Message::Failed(i, error) => {
+                    progress.report(Event::FileFailed {
+                        url: &downloads[i].url,
+                        error: &error,
+                    });
+                    errors.push((i, error));
+                }
+            }
+        }
+
+        while let Some(result) = tasks.join_next().await {
+            if let Err(e) = result {
+                if e.is_panic() {
+                    panic::resume_unwind(e.into_panic());
+                }
+            }
+        }
+
+        errors
+    }
+
+    /// Runs the downloads on `workers` threads, the blocking transport
+    /// can't make progress on several downloads from one thread.
+    #[cfg(not(feature = "tokio"))]
+    async fn run_downloads(
+        &self,
+        downloads: &[Download],
+        workers: usize,
+        options: &DownloadOptions,
+        progress: &dyn Progress,
+    ) -> Vec<(usize, Error)> {
+        let next = AtomicUsize::new(0);
+        let errors = Mutex::new(Vec::new());
+        // worker threads log inside the caller span
+        let span = Span::current();
+
+        thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| {
+                    let _guard = span.enter();
+
+                    runtime::block_on(async {
+                        loop {
+                            if options.cancel.is_cancelled() {
+                                break;
+                            }
+
+                            let i = next.fetch_add(1, Ordering::Relaxed);
+                            let Some(download) = downloads.get(i) else {
+                                break;
+                            };
+
+                            if let Err(e) = download.run(self, options, progress).await {
+                                errors.lock().unwrap().push((i, e));
+                            }
+                        }
+                    })
+                });
+            }
+        });
+
+        errors.into_inner().unwrap()
+    }
+
+    pub(crate) async fn download_all_async(
+        &self,
+        downloads: &[Download],
+        options: &DownloadOptions,
+        progress: &dyn Progress,
+    ) -> Result<()> {
+        progress.report(Event::DownloadsStarted {
+            files: downloads.len(),
+            total_bytes: downloads.iter().filter_map(|d| d.size).sum(),
+        });
+
+        let workers = options.workers.clamp(1, downloads.len().max(1));
+        debug!(
+            "downloading {} files with {} workers",
+            downloads.len(),
+            workers
+        );
+
+        let mut errors = self
+            .run_downloads(downloads, workers, options, progress)
+            .await;
+
+        options.cancel.check()?;
+
+        if errors.is_empty() {
+            return Ok(());
+        }
+
+        errors.sort_by_key(|(i, _)| *i);
+        let failures = errors
+            .into_iter()
+            .map(|(i, e)| (downloads[i].url.clone(), e))
+            .collect();
+
+        Err(Error::DownloadsFailed {
+            total: downloads.len(),
+            failures,
+        })
+    }
+
+    /// Downloads every file using at most `options.workers` concurrent connections.
+    /// Failures don't stop the other downloads, they are reported together
+    /// in the same order as `downloads`.
+    pub fn download_all(
+        &self,
+        downloads: &[Download],
+        options: &DownloadOptions,
+        progress: &dyn Progress,
+    ) -> Result<()> {
+        runtime::block_on(self.download_all_async(downloads, options, progress))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::{
+        http::{Fixture, MemoryTransport},
+        launcher::tests::get_launcher,
+        progress::NoProgress,
+    };
+
+    fn get_url(url: &str) -> Url {
+        Url::parse(url).unwrap()
+    }
+
+    fn get_options() -> DownloadOptions {
+        DownloadOptions {
+            retries: 2,
+            initial_backoff: Duration::ZERO,
+            mirrors: vec![
+                Mirror {
+                    url: get_url("https://libraries.minecraft.net/"),
+                    mirror_url: get_url("https://mirror.example.com/maven/"),
+                },
+                Mirror {
+                    url: get_url("https://resources.download.minecraft.net/"),
+                    mirror_url: get_url("https://mirror.example.com/assets/"),
+                },
+            ],
+            ..DownloadOptions::default()
+        }
+    }
+
+    #[test]
+    fn candidates_start_with_the_original_url() {
+        let url = get_url(
+            "https://libraries.minecraft.net/com/mojang/brigadier/1.0.18/brigadier-1.0.18.jar",
+        );
+
+        assert_eq!(
+            get_options().get_candidates(&url),
+            [
+                url,
+                get_url("https://mirror.example.com/maven/com/mojang/brigadier/1.0.18/brigadier-1.0.18.jar")
+            ]
+        );
+    }
+
+    #[test]
+    fn candidates_without_mirror() {
+        let url = get_url("https://piston-data.mojang.com/v1/objects/abc/client.jar");
+
+        assert_eq!(get_options().get_candidates(&url), [url]);
+    }
+
+    #[test]
+    fn backoff_doubles() {
+        let options = DownloadOptions {
+            initial_backoff: Duration::from_millis(500),
+            ..DownloadOptions::default()
+        };
+
+        let backoffs: Vec<u128> = (1..=4)
+            .map(|attempt| options.get_backoff(attempt).as_millis())
+            .collect();
+        assert_eq!(backoffs, [500, 1000, 2000, 4000]);
+        // saturates instead of overflowing
+        assert_eq!(
+            options.get_backoff(200),
+            Duration::from_millis(500) * u32::MAX
+        );
+    }
+
+    #[test]
+    fn failures_are_collected_in_order() {
+        let (dir, launcher, transport) = get_launcher();
+        let downloads: Vec<Download> = (0..6)
+            .map(|i| Download {
+                url: get_url(&format!("https://example.com/{i}")),
+                path: dir.path().join(i.to_string()),
+                sha1: None,
+                size: None,
+            })
+            .collect();
+        // every odd file is missing
+        for download in downloads.iter().step_by(2) {
+            transport.respond("GET", &download.url, 200, "content");
+        }
+
+        let options = DownloadOptions {
+            workers: 2,
+            ..DownloadOptions::default()
+        };
+        let result = launcher.download_all(&downloads, &options, &NoProgress);
+
+        match result {
+            Err(Error::DownloadsFailed { total, failures }) => {
+                assert_eq!(total, 6);
+                let urls: Vec<&str> = failures.iter().map(|(url, _)| url.path()).collect();
+                assert_eq!(urls, ["/1", "/3", "/5"]);
+            }
+            _ => panic!("expected the missing files to fail"),
+        }
+        assert!(downloads[4].path.is_file());
+    }
+
+    /// collects the urls of the Retrying events
+    #[derive(Default)]
+    struct RetryLog(Mutex<Vec<Url>>);
+
+    impl Progress for RetryLog {
+        fn report(&self, event: Event) {
+            if let Event::Retrying { url, .. } = event {
+                self.0.lock().unwrap().push(url.clone());
+            }
+        }
+    }
+
+    #[test]
+    fn retriable_errors() {
+        let url = get_url("https://example.com/");
+        let status = |status| Error::Status {
+            url: url.clone(),
+            status,
+        };
+
+        assert!(is_retriable(&status(503)));
+        assert!(is_retriable(&status(429)));
+        assert!(is_retriable(&status(408)));
+        assert!(!is_retriable(&status(404)));
+        assert!(!is_retriable(&status(403)));
+        assert!(!is_retriable(&Error::Cancelled));
+        assert!(is_retriable(&Error::ChecksumMismatch { url }));
+    }
+
+    #[test]
+    fn server_error_is_retried() {
+        let (dir, launcher, transport) = get_launcher();
+        let url = get_url("https://example.com/file.txt");
+        transport.respond("GET", &url, 503, "");
+        transport.respond("GET", &url, 200, "content");
+
+        let path = dir.path().join("file.txt");
+        let progress = RetryLog::default();
+        launcher
+            .download_file(&url, &path, None, Some(7), &get_options(), &progress)
+            .unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "content");
+        assert_eq!(transport.get_requests().len(), 2);
+        assert_eq!(*progress.0.lock().unwrap(), [url]);
+    }
+
+    #[test]
+    fn retries_are_limited() {
+        let (dir, launcher, transport) = get_launcher();
+        let url = get_url("https://example.com/file.txt");
+        transport.respond("GET", &url, 500, "");
+
+        let path = dir.path().join("file.txt");
+        let result = launcher.download_file(&url, &path, None, None, &get_options(), &NoProgress);
+
+        assert!(matches!(result, Err(Error::Status { status: 500, .. })));
+        // the first attempt and 2 retries
+        assert_eq!(transport.get_requests().len(), 3);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn not_found_moves_to_the_next_mirror() {
+        let (dir, launcher, transport) = get_launcher();
+        let url = get_url("https://example.com/file.txt");
+        let mirror_url = get_url("https://mirror.example.com/file.txt");
+        transport.respond("GET", &mirror_url, 200, "content");
+
+        let options = DownloadOptions {
+            mirrors: vec![Mirror {
+                url: get_url("https://example.com/"),
+                mirror_url: get_url("https://mirror.example.com/"),
+            }],
+            ..get_options()
+        };
+        let path = dir.path().join("file.txt");
+        let progress = RetryLog::default();
+        launcher
+            .download_file(&url, &path, None, None, &options, &progress)
+            .unwrap();
+
+        let urls: Vec<Url> = transport
+            .get_requests()
+            .into_iter()
+            .map(|request| request.url)
+            .collect();
+        assert_eq!(urls, [url, mirror_url]);
+        assert!(progress.0.lock().unwrap().is_empty());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "content");
+    }
+
+    /// sha1 of "content"
+    const SHA1: &str = "040f06fd774092478d450774f5ba30c5da78acc8";
+
+    #[test]
+    fn checksum_mismatch() {
+        let (dir, launcher, transport) = get_launcher();
+        let url = get_url("https://example.com/file.txt");
+        transport.respond("GET", &url, 200, "corrupted");
+
+        let path = dir.path().join("file.txt");
+        let result =
+            launcher.download_file(&url, &path, Some(SHA1), None, &get_options(), &NoProgress);
+
+        assert!(matches!(result, Err(Error::ChecksumMismatch { .. })));
+        assert!(!path.exists());
+        assert!(!get_part_path(&path).exists());
+    }
+
+    /// cancels its token when a download reports data or a retry
+    struct CancelOnEvent(CancellationToken);
+
+    impl Progress for CancelOnEvent {
+        fn report(&self, event: Event) {
+            if let Event::BytesTransferred { .. } | Event::Retrying { .. } = event {
+                self.0.cancel();
+            }
+        }
+    }
+
+    #[test]
+    fn cancelled_download_removes_the_part_file() {
+        let (dir, launcher, transport) = get_launcher();
+        let url = get_url("https://example.com/file.txt");
+        transport.respond("GET", &url, 200, "content");
+
+        let options = get_options();
+        let path = dir.path().join("file.txt");
+        let progress = CancelOnEvent(options.cancel.clone());
+        let result = launcher.download_file(&url, &path, None, None, &options, &progress);
+
+        assert!(matches!(result, Err(Error::Cancelled)));
+        assert!(!path.exists());
+        assert!(!get_part_path(&path).exists());
+    }
+
+    #[test]
+    fn cancelled_backoff_removes_the_part_file() {
+        let (dir, launcher, transport) = get_launcher();
+        let url = get_url("https://example.com/file.txt");
+        transport.respond("GET", &url, 503, "");
+
+        // left by an interrupted download
+        let path = dir.path().join("file.txt");
+        fs::write(get_part_path(&path), "cont").unwrap();
+
+        let options = get_options();
+        let progress = CancelOnEvent(options.cancel.clone());
+        let result = launcher.download_file(&url, &path, None, None, &options, &progress);
+
+        assert!(matches!(result, Err(Error::Cancelled)));
+        assert_eq!(transport.get_requests().len(), 1);
+        assert!(!get_part_path(&path).exists());
+    }
+
+    /// collects the bytes of the BytesTransferred events
+    #[derive(Default)]
+    struct ByteLog(Mutex<Vec<u64>>);
+
+    impl Progress for ByteLog {
+        fn report(&self, event: Event) {
+            if let Event::BytesTransferred { bytes, .. } = event {
+                self.0.lock().unwrap().push(bytes);
+            }
+        }
+    }
+
+    /// returns the Range header of every request
+    fn get_ranges(transport: &MemoryTransport) -> Vec<Option<String>> {
+        transport
+            .get_requests()
+            .into_iter()
+            .map(|request| {
+                request
+                    .headers
+                    .into_iter()
+                    .find(|(name, _)| name == "Range")
+                    .map(|(_, value)| value)
+            })
+            .collect()
+    }
+
+    fn get_partial_content(url: &Url, range: &str, body: &str) -> Fixture {
+        Fixture {
+            method: "GET".to_string(),
+            url: url.clone(),
+            status: 206,
+            headers: vec![("Content-Range".to_string(), range.to_string())],
+            body: body.to_string(),
+        }
+    }
+
+    #[test]
+    fn partial_download_is_resumed() {
+        let (dir, launcher, transport) = get_launcher();
+        let url = get_url("https://example.com/file.txt");
+        transport.add_fixture(get_partial_content(&url, "bytes 4-6/7", "ent"));
+
+        let path = dir.path().join("file.txt");
+        fs::write(get_part_path(&path), "cont").unwrap();
+        let progress = ByteLog::default();
+        launcher
+            .download_file(&url, &path, Some(SHA1), Some(7), &get_options(), &progress)
+            .unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "content");
+        assert_eq!(get_ranges(&transport), [Some("bytes=4-".to_string())]);
+        // the resumed offset, then the new bytes
+        assert_eq!(*progress.0.lock().unwrap(), [4, 3]);
+    }
+
+    #[test]
+    fn ignored_range_replaces_the_part_file() {
+        let (dir, launcher, transport) = get_launcher();
+        let url = get_url("https://example.com/file.txt");
+        transport.respond("GET", &url, 200, "content");
+
+        let path = dir.path().join("file.txt");
+        fs::write(get_part_path(&path), "cont").unwrap();
+        let progress = ByteLog::default();
+        launcher
+            .download_file(&url, &path, Some(SHA1), None, &get_options(), &progress)
+            .unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "content");
+        assert_eq!(*progress.0.lock().unwrap(), [7]);
+    }
+
+    #[test]
+    fn unsatisfiable_range_starts_over() {
+        let (dir, launcher, transport) = get_launcher();
+        let url = get_url("https://example.com/file.txt");
+        transport.respond("GET", &url, 416, "");
+        transport.respond("GET", &url, 200, "content");
+
+        // longer than the remote file
+        let path = dir.path().join("file.txt");
+        fs::write(get_part_path(&path), "content and more").unwrap();
+        launcher
+            .download_file(&url, &path, Some(SHA1), None, &get_options(), &NoProgress)
+            .unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "content");
+        assert_eq!(
+            get_ranges(&transport),
+            [Some("bytes=16-".to_string()), None]
+        );
+    }
+
+    #[test]
+    fn corrupted_resume_starts_over() {
+        let (dir, launcher, transport) = get_launcher();
+        let url = get_url("https://example.com/file.txt");
+        transport.add_fixture(get_partial_content(&url, "bytes 4-6/7", "ent"));
+        transport.respond("GET", &url, 200, "content");
+
+        let path = dir.path().join("file.txt");
+        fs::write(get_part_path(&path), "bad!").unwrap();
+        let progress = RetryLog::default();
+        launcher
+            .download_file(&url, &path, Some(SHA1), None, &get_options(), &progress)
+            .unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "content");
+        assert_eq!(get_ranges(&transport), [Some("bytes=4-".to_string()), None]);
+        // started over within the same attempt
+        assert!(progress.0.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,62 @@
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+const VERSION_MANIFEST_URL: &str =
+    "https://piston-meta.mojang.com/mc/game/version_manifest_v2.json";
+const RESOURCES_URL: &str = "https://resources.download.minecraft.net/";
+pub(crate) const LIBRARIES_URL: &str = "https://libraries.minecraft.net/";
+const FABRIC_META_URL: &str = "https://meta.fabricmc.net/v2/";
+const QUILT_META_URL: &str = "https://meta.quiltmc.org/v3/";
+const FORGE_MAVEN_URL: &str = "https://maven.minecraftforge.net/";
+const NEOFORGE_MAVEN_URL: &str = "https://maven.neoforged.net/releases/";
+const MICROSOFT_OAUTH_URL: &str = "https://login.microsoftonline.com/consumers/oauth2/v2.0/";
+const XBL_AUTH_URL: &str = "https://user.auth.xboxlive.com/";
+const XSTS_AUTH_URL: &str = "https://xsts.auth.xboxlive.com/";
+const MINECRAFT_SERVICES_URL: &str = "https://api.minecraftservices.com/";
+
+/// Remote servers used by the launcher, base urls must end with a slash.
+///
+/// The version JSONs and their client jars and asset indexes come from the urls listed
+/// in the version manifest (piston-meta and piston-data.mojang.com), use a Mirror
+/// to fetch them from another host.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Endpoints {
+    pub version_manifest: Url,
+    /// asset objects, served at <hash[..2]>/<hash>
+    pub resources: Url,
+    /// maven repository for libraries without a download url,
+    /// also replaces libraries.minecraft.net in the listed download urls
+    pub libraries: Url,
+    pub fabric_meta: Url,
+    pub quilt_meta: Url,
+    pub forge_maven: Url,
+    pub neoforge_maven: Url,
+    /// serves authorize and token
+    pub microsoft_oauth: Url,
+    pub xbl_auth: Url,
+    pub xsts_auth: Url,
+    pub minecraft_services: Url,
+}
+
+fn parse(url: &str) -> Url {
+    Url::parse(url).unwrap()
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Self {
+            version_manifest: parse(VERSION_MANIFEST_URL),
+            resources: parse(RESOURCES_URL),
+            libraries: parse(LIBRARIES_URL),
+            fabric_meta: parse(FABRIC_META_URL),
+            quilt_meta: parse(QUILT_META_URL),
+            forge_maven: parse(FORGE_MAVEN_URL),
+            neoforge_maven: parse(NEOFORGE_MAVEN_URL),
+            microsoft_oauth: parse(MICROSOFT_OAUTH_URL),
+            xbl_auth: parse(XBL_AUTH_URL),
+            xsts_auth: parse(XSTS_AUTH_URL),
+            minecraft_services: parse(MINECRAFT_SERVICES_URL),
+        }
+    }
+}
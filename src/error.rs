@@ -0,0 +1,125 @@
+use std::{env::JoinPathsError, error::Error as StdError, io, path::PathBuf};
+
+use thiserror::Error;
+use url::Url;
+use zip::result::ZipError;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Errors returned by the launcher, the wrapped errors are reachable through `source()`
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// a JSON file or response doesn't have the expected shape
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Url(#[from] url::ParseError),
+    #[error(transparent)]
+    Zip(#[from] ZipError),
+    #[error(transparent)]
+    JoinPaths(#[from] JoinPathsError),
+    #[error("error setting up the http client")]
+    HttpClient(#[source] Box<dyn StdError + Send + Sync>),
+    /// the request didn't get a response (dns, connection, tls, timeout...)
+    #[error("error requesting {url}")]
+    Network {
+        url: Url,
+        #[source]
+        source: Box<dyn StdError + Send + Sync>,
+    },
+    #[error("{url} returned status {status}")]
+    Status { url: Url, status: u16 },
+    #[error("downloaded file {url} has a wrong checksum")]
+    ChecksumMismatch { url: Url },
+    /// failures are in the same order as the requested downloads
+    #[error(
+        "{} of {} downloads failed:\n{}",
+        .failures.len(),
+        .total,
+        format_failures(.failures)
+    )]
+    DownloadsFailed {
+        total: usize,
+        failures: Vec<(Url, Error)>,
+    },
+    #[error("{0} is missing and has no download url")]
+    MissingArtifact(String),
+    #[error("instance {0} not found")]
+    InstanceNotFound(String),
+    #[error("account {0} not found")]
+    AccountNotFound(String),
+    /// the version manifest was not downloaded
+    #[error("version {0} is not installed")]
+    VersionNotFound(String),
+    #[error("invalid version manifest: {0}")]
+    InvalidManifest(String),
+    #[error("unresolved launch variables: {}", .0.join(", "))]
+    UnresolvedVariables(Vec<String>),
+    #[error("loader installer failed: {0}")]
+    Installer(String),
+    #[error(transparent)]
+    Auth(#[from] AuthError),
+    #[error("could not find the user home directory")]
+    NoHomeDir,
+    #[error("invalid path {0:?}")]
+    InvalidPath(PathBuf),
+    #[error("operation cancelled")]
+    Cancelled,
+}
+
+/// Reasons a Microsoft account can't be used to play
+#[derive(Debug, Error)]
+pub enum AuthError {
+    #[error("the login callback has an invalid state")]
+    InvalidState,
+    #[error("the login callback has no authorization code")]
+    MissingCode,
+    #[error("this Microsoft account has no Xbox account, sign in on xbox.com to create one")]
+    NoXboxAccount,
+    #[error("Xbox Live is not available in the country of this account")]
+    XboxUnavailable,
+    #[error("this account needs adult verification on xbox.com")]
+    AdultVerificationRequired,
+    #[error("this is a child account, an adult must add it to a Microsoft family")]
+    ChildAccount,
+    /// XErr code returned by the Xbox authorization service
+    #[error("Xbox Live authorization failed with error {0}")]
+    Xbox(u64),
+    #[error("Xbox Live authorized the account without a user hash")]
+    MissingUserHash,
+    #[error("this account doesn't own Minecraft")]
+    NoMinecraftProfile,
+    /// the stored refresh token can't be used anymore, the account must be added again
+    #[error("the Microsoft sign-in expired, add the account again")]
+    SignInExpired,
+    #[error("the device code expired before the sign-in was completed")]
+    DeviceCodeExpired,
+    #[error("the user declined the sign-in")]
+    SignInDeclined,
+    /// error code of a token request refused by Microsoft, e.g. invalid_client
+    #[error("the Microsoft sign-in failed with error {0}")]
+    OAuth(String),
+}
+
+/// formats error followed by its sources, like "a: b: c"
+pub fn format_chain(error: &dyn StdError) -> String {
+    let mut message = error.to_string();
+
+    let mut source = error.source();
+    while let Some(error) = source {
+        message.push_str(&format!(": {error}"));
+        source = error.source();
+    }
+
+    message
+}
+
+fn format_failures(failures: &[(Url, Error)]) -> String {
+    failures
+        .iter()
+        .map(|(url, error)| format!("{url}: {}", format_chain(error)))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
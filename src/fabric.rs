@@ -0,0 +1,187 @@
+use serde::Deserialize;
+use serde_json::Value;
+use url::Url;
+
+use crate::{error::Result, loaders::LoaderVersion, runtime, Launcher};
+
+#[derive(Deserialize)]
+struct LoaderInfo {
+    version: String,
+    #[serde(default)]
+    stable: bool,
+}
+
+#[derive(Deserialize)]
+struct LoaderEntry {
+    loader: LoaderInfo,
+}
+
+impl Launcher {
+    pub(crate) async fn get_fabric_loader_versions_async(
+        &self,
+        minecraft_version: &str,
+    ) -> Result<Vec<LoaderVersion>> {
+        let meta_url = self.get_config().endpoints.fabric_meta;
+        let url = meta_url.join(&format!("versions/loader/{minecraft_version}"))?;
+        let resp: Vec<LoaderEntry> = self.get_json(&url).await?;
+
+        let versions = resp
+            .into_iter()
+            .map(|entry| LoaderVersion {
+                version: entry.loader.version,
+                stable: entry.loader.stable,
+            })
+            .collect();
+
+        Ok(versions)
+    }
+
+    /// returns the loader versions compatible with minecraft_version, newest first
+    pub fn get_fabric_loader_versions(
+        &self,
+        minecraft_version: &str,
+    ) -> Result<Vec<LoaderVersion>> {
+        runtime::block_on(self.get_fabric_loader_versions_async(minecraft_version))
+    }
+
+    pub(crate) async fn download_fabric_profile_async(
+        &self,
+        minecraft_version: &str,
+        loader_version: &str,
+    ) -> Result<String> {
+        let meta_url = self.get_config().endpoints.fabric_meta;
+        self.download_loader_profile(&meta_url, minecraft_version, loader_version)
+            .await
+    }
+
+    /// returns the profile version id
+    pub fn download_fabric_profile(
+        &self,
+        minecraft_version: &str,
+        loader_version: &str,
+    ) -> Result<String> {
+        runtime::block_on(self.download_fabric_profile_async(minecraft_version, loader_version))
+    }
+
+    /// downloads the launcher profile from a fabric-like meta server and stores it
+    /// as a version manifest, returns the profile version id
+    pub(crate) async fn download_loader_profile(
+        &self,
+        meta_url: &Url,
+        minecraft_version: &str,
+        loader_version: &str,
+    ) -> Result<String> {
+        let url = meta_url.join(&format!(
+            "versions/loader/{minecraft_version}/{loader_version}/profile/json"
+        ))?;
+        let profile: Value = self.get_json(&url).await?;
+
+        self.write_minecraft_manifest(&profile)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        cancel::CancellationToken, launcher::tests::get_launcher, launchermeta::Version,
+        loaders::LoaderKind,
+    };
+
+    fn get_url(url: &str) -> Url {
+        Url::parse(url).unwrap()
+    }
+
+    fn get_profile() -> Value {
+        json!({
+            "id": "fabric-loader-0.15.3-1.20.4",
+            "inheritsFrom": "1.20.4",
+            "mainClass": "net.fabricmc.loader.impl.launch.knot.KnotClient",
+            "libraries": [{
+                "name": "net.fabricmc:fabric-loader:0.15.3",
+                "url": "https://maven.fabricmc.net/"
+            }]
+        })
+    }
+
+    #[test]
+    fn loader_versions() {
+        let (_dir, launcher, transport) = get_launcher();
+        let url = get_url("https://meta.fabricmc.net/v2/versions/loader/1.20.4");
+        let body = json!([
+            { "loader": { "version": "0.15.3", "stable": true } },
+            { "loader": { "version": "0.15.0-beta.1" } }
+        ]);
+        transport.respond("GET", &url, 200, body.to_string());
+
+        let versions: Vec<(String, bool)> = launcher
+            .get_fabric_loader_versions("1.20.4")
+            .unwrap()
+            .into_iter()
+            .map(|version| (version.version, version.stable))
+            .collect();
+
+        assert_eq!(
+            versions,
+            [
+                ("0.15.3".to_string(), true),
+                ("0.15.0-beta.1".to_string(), false)
+            ]
+        );
+    }
+
+    #[test]
+    fn profile_is_stored_as_a_manifest() {
+        let (dir, launcher, transport) = get_launcher();
+        let url =
+            get_url("https://meta.fabricmc.net/v2/versions/loader/1.20.4/0.15.3/profile/json");
+        transport.respond("GET", &url, 200, get_profile().to_string());
+
+        let version_id = launcher
+            .download_fabric_profile("1.20.4", "0.15.3")
+            .unwrap();
+
+        assert_eq!(version_id, "fabric-loader-0.15.3-1.20.4");
+        let path = dir
+            .path()
+            .join("meta/net.minecraft/fabric-loader-0.15.3-1.20.4.json");
+        let manifest: Value =
+            serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        assert_eq!(manifest, get_profile());
+    }
+
+    #[test]
+    fn new_instance_records_the_loader() {
+        let (_dir, launcher, transport) = get_launcher();
+        let version = Version {
+            id: "1.20.4".to_string(),
+            r#type: "release".to_string(),
+            url: get_url("https://piston-meta.mojang.com/v1/packages/abc/1.20.4.json"),
+            sha1: None,
+        };
+        let vanilla = json!({ "id": "1.20.4", "mainClass": "vanilla" });
+        transport.respond("GET", &version.url, 200, vanilla.to_string());
+        let url =
+            get_url("https://meta.fabricmc.net/v2/versions/loader/1.20.4/0.15.3/profile/json");
+        transport.respond("GET", &url, 200, get_profile().to_string());
+
+        launcher
+            .new_instance(
+                "fabric",
+                &version,
+                Some((LoaderKind::Fabric, "0.15.3")),
+                &CancellationToken::new(),
+            )
+            .unwrap();
+
+        let config = launcher.read_instance_config("fabric").unwrap();
+        assert_eq!(config.minecraft_version, "1.20.4");
+        assert_eq!(config.version_type, "Fabric");
+        let loader = config.loader.unwrap();
+        assert!(loader.kind == LoaderKind::Fabric);
+        assert_eq!(loader.version, "0.15.3");
+        assert_eq!(loader.version_id, "fabric-loader-0.15.3-1.20.4");
+    }
+}
@@ -0,0 +1,653 @@
+use std::{
+    collections::HashMap,
+    env,
+    fs::{self, File},
+    io::{self, Read},
+    path::{Component, Path, PathBuf},
+    process::Command,
+};
+
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::{debug, info, info_span, Instrument};
+use url::Url;
+use zip::ZipArchive;
+
+use crate::{
+    cancel::CancellationToken,
+    download::{self, DownloadOptions},
+    endpoints::Endpoints,
+    error::{Error, Result},
+    http::HttpRequest,
+    launchermeta::Library,
+    libraries,
+    loaders::{LoaderKind, LoaderVersion},
+    progress::NoProgress,
+    runtime, Launcher,
+};
+
+#[derive(Deserialize)]
+struct DataEntry {
+    client: String,
+}
+
+#[derive(Deserialize)]
+struct Processor {
+    jar: String,
+    #[serde(default)]
+    classpath: Vec<String>,
+    #[serde(default)]
+    args: Vec<String>,
+    sides: Option<Vec<String>>,
+    /// output file -> expected sha1
+    #[serde(default)]
+    outputs: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct InstallProfile {
+    /// path of the version JSON inside the installer
+    json: String,
+    minecraft: String,
+    #[serde(default)]
+    data: HashMap<String, DataEntry>,
+    #[serde(default)]
+    processors: Vec<Processor>,
+    #[serde(default)]
+    libraries: Vec<Library>,
+}
+
+fn get_maven_url(kind: LoaderKind, endpoints: &Endpoints) -> &Url {
+    match kind {
+        LoaderKind::NeoForge => &endpoints.neoforge_maven,
+        _ => &endpoints.forge_maven,
+    }
+}
+
+/// returns the maven group path of the installer artifacts
+fn get_artifact_group(kind: LoaderKind) -> &'static str {
+    match kind {
+        LoaderKind::NeoForge => "net/neoforged/neoforge",
+        _ => "net/minecraftforge/forge",
+    }
+}
+
+/// returns the full maven version, forge prefixes it with the minecraft version
+fn get_full_version(kind: LoaderKind, minecraft_version: &str, loader_version: &str) -> String {
+    match kind {
+        LoaderKind::NeoForge => loader_version.to_string(),
+        _ => format!("{minecraft_version}-{loader_version}"),
+    }
+}
+
+/// neoforge versions drop the leading "1." of the minecraft version,
+/// e.g. 1.20.4 -> 20.4.x and 1.21 -> 21.0.x
+fn get_neoforge_prefix(minecraft_version: &str) -> String {
+    let version = minecraft_version
+        .strip_prefix("1.")
+        .unwrap_or(minecraft_version);
+
+    match version.contains('.') {
+        true => format!("{version}."),
+        false => format!("{version}.0."),
+    }
+}
+
+fn read_entry(archive: &mut ZipArchive<File>, name: &str) -> Result<String> {
+    let mut file = archive
+        .by_name(name.trim_start_matches('/'))
+        .map_err(|e| Error::Installer(format!("error reading {name} from installer: {e}")))?;
+    let mut data = String::new();
+    file.read_to_string(&mut data)?;
+
+    Ok(data)
+}
+
+fn extract_entry(archive: &mut ZipArchive<File>, name: &str, path: &Path) -> Result<()> {
+    let mut file = archive.by_name(name.trim_start_matches('/'))?;
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut outfile = File::create(path)?;
+    io::copy(&mut file, &mut outfile)?;
+
+    Ok(())
+}
+
+fn get_main_class(jar_path: &Path) -> Result<String> {
+    let mut archive = ZipArchive::new(File::open(jar_path)?)?;
+    let manifest = read_entry(&mut archive, "META-INF/MANIFEST.MF")?;
+
+    manifest
+        .lines()
+        .find_map(|line| line.strip_prefix("Main-Class:"))
+        .map(|main_class| main_class.trim().to_string())
+        .ok_or_else(|| Error::Installer(format!("{:?} has no Main-Class", jar_path)))
+}
+
+impl Launcher {
+    fn get_installer_tmp_dir(&self) -> PathBuf {
+        self.get_root_dir().join("installer-tmp")
+    }
+
+    pub(crate) async fn get_forge_loader_versions_async(
+        &self,
+        kind: LoaderKind,
+        minecraft_version: &str,
+    ) -> Result<Vec<LoaderVersion>> {
+        let path = format!("{}/maven-metadata.xml", get_artifact_group(kind));
+        let url = get_maven_url(kind, &self.get_config().endpoints).join(&path)?;
+        let metadata = self
+            .send_request(HttpRequest::get(&url))
+            .await?
+            .into_string()
+            .await?;
+
+        let prefix = match kind {
+            LoaderKind::NeoForge => get_neoforge_prefix(minecraft_version),
+            _ => format!("{minecraft_version}-"),
+        };
+
+        let mut versions: Vec<LoaderVersion> = metadata
+            .split("<version>")
+            .skip(1)
+            .filter_map(|chunk| chunk.split_once("</version>"))
+            .map(|(version, _)| version.trim())
+            .filter(|version| version.starts_with(&prefix))
+            .map(|version| LoaderVersion {
+                version: match kind {
+                    LoaderKind::NeoForge => version.to_string(),
+                    _ => version[prefix.len()..].to_string(),
+                },
+                stable: !version.contains("beta"),
+            })
+            .collect();
+        versions.reverse();
+
+        Ok(versions)
+    }
+
+    /// returns the loader versions available for minecraft_version, newest first
+    pub fn get_forge_loader_versions(
+        &self,
+        kind: LoaderKind,
+        minecraft_version: &str,
+    ) -> Result<Vec<LoaderVersion>> {
+        runtime::block_on(self.get_forge_loader_versions_async(kind, minecraft_version))
+    }
+
+    async fn download_forge_installer(
+        &self,
+        kind: LoaderKind,
+        full_version: &str,
+        endpoints: &Endpoints,
+        options: &DownloadOptions,
+    ) -> Result<PathBuf> {
+        let group = get_artifact_group(kind);
+        let name = group.rsplit('/').next().unwrap_or_default();
+        let path = format!("{group}/{full_version}/{name}-{full_version}-installer.jar");
+
+        let url = get_maven_url(kind, endpoints).join(&path)?;
+        let installer_path = self.get_libraries_dir().join(path);
+        debug!("downloading installer {}", url);
+        self.download_file_async(&url, &installer_path, None, None, options, &NoProgress)
+            .await?;
+
+        Ok(installer_path)
+    }
+
+    /// extracts the artifacts bundled in the installer maven/ directory
+    fn extract_bundled_libraries(&self, archive: &mut ZipArchive<File>) -> Result<()> {
+        let mut entries = Vec::new();
+        for i in 0..archive.len() {
+            let file = archive.by_index(i)?;
+            if file.is_dir() {
+                continue;
+            }
+
+            // like extract_natives, enclosed_name skips the entries escaping the archive,
+            // and the rest of the path must not climb out of maven/ either
+            let path = match file.enclosed_name() {
+                Some(path) => match path.strip_prefix("maven") {
+                    Ok(path) if path.components().all(|c| matches!(c, Component::Normal(_))) => {
+                        self.get_libraries_dir().join(path)
+                    }
+                    _ => continue,
+                },
+                None => continue,
+            };
+            entries.push((file.name().to_string(), path));
+        }
+
+        for (name, path) in entries {
+            if !path.is_file() {
+                extract_entry(archive, &name, &path)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn get_library_path(&self, coords: &str) -> Result<PathBuf> {
+        let path = libraries::get_maven_path(coords)
+            .ok_or_else(|| Error::Installer(format!("invalid maven coordinates: {coords}")))?;
+
+        Ok(self.get_libraries_dir().join(path))
+    }
+
+    /// resolves a data value: [coords] is a library, 'text' is a literal
+    /// and /path is a file to extract from the installer
+    fn resolve_data_value(&self, archive: &mut ZipArchive<File>, value: &str) -> Result<String> {
+        if let Some(coords) = value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+            return Ok(self.get_library_path(coords)?.to_string_lossy().to_string());
+        }
+
+        if let Some(literal) = value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')) {
+            return Ok(literal.to_string());
+        }
+
+        if value.starts_with('/') {
+            let path = self
+                .get_installer_tmp_dir()
+                .join(value.trim_start_matches('/'));
+            extract_entry(archive, value, &path)?;
+
+            return Ok(path.to_string_lossy().to_string());
+        }
+
+        Ok(value.to_string())
+    }
+
+    /// replaces {KEY} tokens and [coords] arguments of a processor
+    fn substitute_arg(&self, arg: &str, data: &HashMap<String, String>) -> Result<String> {
+        if let Some(coords) = arg.strip_prefix('[').and_then(|a| a.strip_suffix(']')) {
+            return Ok(self.get_library_path(coords)?.to_string_lossy().to_string());
+        }
+
+        let mut result = String::with_capacity(arg.len());
+        let mut rest = arg;
+
+        while let Some(start) = rest.find('{') {
+            let Some(len) = rest[start + 1..].find('}') else {
+                break;
+            };
+
+            let key = &rest[start + 1..start + 1 + len];
+            let value = data
+                .get(key)
+                .ok_or_else(|| Error::Installer(format!("unknown installer data key: {key}")))?;
+
+            result.push_str(&rest[..start]);
+            result.push_str(value);
+            rest = &rest[start + 2 + len..];
+        }
+        result.push_str(rest);
+
+        Ok(result)
+    }
+
+    async fn run_processor(
+        &self,
+        java_path: &str,
+        processor: &Processor,
+        data: &HashMap<String, String>,
+    ) -> Result<()> {
+        let jar_path = self.get_library_path(&processor.jar)?;
+        let main_class = get_main_class(&jar_path)?;
+
+        let mut class_path = vec![jar_path];
+        for coords in &processor.classpath {
+            class_path.push(self.get_library_path(coords)?);
+        }
+
+        let args = processor
+            .args
+            .iter()
+            .map(|arg| self.substitute_arg(arg, data))
+            .collect::<Result<Vec<String>>>()?;
+
+        info!("running installer processor {}", processor.jar);
+        let mut command = Command::new(java_path);
+        command
+            .arg("-cp")
+            .arg(env::join_paths(class_path)?)
+            .arg(&main_class)
+            .args(args);
+
+        #[cfg(feature = "tokio")]
+        let status = tokio::process::Command::from(command).status().await?;
+        #[cfg(not(feature = "tokio"))]
+        let status = command.status()?;
+
+        if !status.success() {
+            return Err(Error::Installer(format!(
+                "installer processor {} failed with {}",
+                processor.jar, status
+            )));
+        }
+
+        for (file, sha1) in &processor.outputs {
+            let path = self.substitute_arg(file, data)?;
+            let sha1 = self.substitute_arg(sha1, data)?;
+
+            if !download::verify_file_async(Path::new(&path), Some(sha1.trim_matches('\'')), None)
+                .await?
+            {
+                return Err(Error::Installer(format!(
+                    "installer processor {} produced a corrupt {}",
+                    processor.jar, path
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn run_processors(
+        &self,
+        java_path: &str,
+        processors: &[Processor],
+        data: &HashMap<String, String>,
+        cancel: &CancellationToken,
+    ) -> Result<()> {
+        for processor in processors {
+            let is_client = processor
+                .sides
+                .as_ref()
+                .is_none_or(|sides| sides.iter().any(|side| side == "client"));
+
+            if is_client {
+                cancel.check()?;
+                self.run_processor(java_path, processor, data).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) async fn install_forge_async(
+        &self,
+        kind: LoaderKind,
+        minecraft_version: &str,
+        loader_version: &str,
+        cancel: &CancellationToken,
+    ) -> Result<String> {
+        let full_version = get_full_version(kind, minecraft_version, loader_version);
+        let config = self.get_config();
+        let options = self.get_download_options(cancel);
+        let installer_path = self
+            .download_forge_installer(kind, &full_version, &config.endpoints, &options)
+            .await?;
+        let mut archive = ZipArchive::new(File::open(&installer_path)?)?;
+
+        let install_profile: Value =
+            serde_json::from_str(&read_entry(&mut archive, "install_profile.json")?)?;
+        if install_profile.get("json").is_none() {
+            return Err(Error::Installer(
+                "installers for minecraft versions older than 1.13 are not supported".to_string(),
+            ));
+        }
+        let install_profile: InstallProfile = serde_json::from_value(install_profile)?;
+
+        if install_profile.minecraft.ne(minecraft_version) {
+            return Err(Error::Installer(format!(
+                "installer is for minecraft {}, not {}",
+                install_profile.minecraft, minecraft_version
+            )));
+        }
+
+        let version: Value =
+            serde_json::from_str(&read_entry(&mut archive, &install_profile.json)?)?;
+        let version_id = self.write_minecraft_manifest(&version)?;
+
+        let minecraft_meta = self.read_minecraft_manifest(minecraft_version)?;
+        self.download_client_jar_async(&minecraft_meta, &options, &NoProgress)
+            .await?;
+
+        self.extract_bundled_libraries(&mut archive)?;
+        let artifacts: Vec<_> = install_profile
+            .libraries
+            .iter()
+            .filter_map(|lib| libraries::get_lib_artifact(lib, &config.endpoints.libraries))
+            .collect();
+        self.download_artifacts_async(&artifacts, &options, &NoProgress)
+            .await?;
+
+        let installer_tmp_dir = self.get_installer_tmp_dir();
+        if installer_tmp_dir.exists() {
+            fs::remove_dir_all(&installer_tmp_dir)?;
+        }
+
+        let mut data = HashMap::new();
+        for (key, entry) in &install_profile.data {
+            data.insert(
+                key.clone(),
+                self.resolve_data_value(&mut archive, &entry.client)?,
+            );
+        }
+
+        let path_to_string = |path: &Path| path.to_string_lossy().to_string();
+        data.insert("SIDE".to_string(), "client".to_string());
+        data.insert(
+            "MINECRAFT_JAR".to_string(),
+            path_to_string(&self.get_client_jar_path(&minecraft_meta)),
+        );
+        data.insert(
+            "MINECRAFT_VERSION".to_string(),
+            minecraft_version.to_string(),
+        );
+        data.insert("ROOT".to_string(), path_to_string(self.get_root_dir()));
+        data.insert("INSTALLER".to_string(), path_to_string(&installer_path));
+        data.insert(
+            "LIBRARY_DIR".to_string(),
+            path_to_string(&self.get_libraries_dir()),
+        );
+
+        let result = self
+            .run_processors(
+                &config.java.path,
+                &install_profile.processors,
+                &data,
+                cancel,
+            )
+            .instrument(info_span!("processors"))
+            .await;
+
+        // the extracted data files are only needed by the processors
+        fs::remove_dir_all(&installer_tmp_dir).ok();
+        result?;
+
+        Ok(version_id)
+    }
+
+    /// Downloads and runs the forge or neoforge installer headlessly,
+    /// returns the id of the resulting version JSON
+    pub fn install_forge(
+        &self,
+        kind: LoaderKind,
+        minecraft_version: &str,
+        loader_version: &str,
+        cancel: &CancellationToken,
+    ) -> Result<String> {
+        runtime::block_on(self.install_forge_async(kind, minecraft_version, loader_version, cancel))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use zip::{write::FileOptions, ZipWriter};
+
+    use super::*;
+    use crate::launcher::tests::get_launcher;
+
+    /// writes an installer jar with the given entries and opens it
+    fn get_archive(path: &Path, entries: &[(&str, &str)]) -> ZipArchive<File> {
+        let mut writer = ZipWriter::new(File::create(path).unwrap());
+        for (name, content) in entries {
+            writer.start_file(*name, FileOptions::default()).unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap();
+
+        ZipArchive::new(File::open(path).unwrap()).unwrap()
+    }
+
+    /// returns the (version, stable) pairs
+    fn get_versions(versions: Vec<LoaderVersion>) -> Vec<(String, bool)> {
+        versions
+            .into_iter()
+            .map(|version| (version.version, version.stable))
+            .collect()
+    }
+
+    const MAVEN_METADATA: &str = "<metadata>
+  <groupId>net.minecraftforge</groupId>
+  <versioning>
+    <versions>
+      <version>1.20.1-47.0.0-beta</version>
+      <version>1.20.1-47.2.0</version>
+      <version>1.20.4-49.0.3</version>
+      <version>20.4.80-beta</version>
+      <version>20.4.237</version>
+      <version>21.0.10</version>
+    </versions>
+  </versioning>
+</metadata>";
+
+    #[test]
+    fn neoforge_prefix() {
+        assert_eq!(get_neoforge_prefix("1.20.4"), "20.4.");
+        assert_eq!(get_neoforge_prefix("1.21"), "21.0.");
+    }
+
+    #[test]
+    fn forge_versions_drop_the_minecraft_version() {
+        let (_dir, launcher, transport) = get_launcher();
+        let url = Url::parse(
+            "https://maven.minecraftforge.net/net/minecraftforge/forge/maven-metadata.xml",
+        )
+        .unwrap();
+        transport.respond("GET", &url, 200, MAVEN_METADATA);
+
+        let versions = launcher
+            .get_forge_loader_versions(LoaderKind::Forge, "1.20.1")
+            .unwrap();
+
+        assert_eq!(
+            get_versions(versions),
+            [
+                ("47.2.0".to_string(), true),
+                ("47.0.0-beta".to_string(), false)
+            ]
+        );
+    }
+
+    #[test]
+    fn neoforge_versions_match_the_minecraft_version() {
+        let (_dir, launcher, transport) = get_launcher();
+        let url = Url::parse(
+            "https://maven.neoforged.net/releases/net/neoforged/neoforge/maven-metadata.xml",
+        )
+        .unwrap();
+        transport.respond("GET", &url, 200, MAVEN_METADATA);
+
+        let versions = launcher
+            .get_forge_loader_versions(LoaderKind::NeoForge, "1.20.4")
+            .unwrap();
+
+        assert_eq!(
+            get_versions(versions),
+            [
+                ("20.4.237".to_string(), true),
+                ("20.4.80-beta".to_string(), false)
+            ]
+        );
+    }
+
+    #[test]
+    fn args_are_substituted() {
+        let (dir, launcher, _) = get_launcher();
+        let data = HashMap::from([
+            ("SIDE".to_string(), "client".to_string()),
+            ("MAPPINGS".to_string(), "/tmp/mappings.txt".to_string()),
+        ]);
+
+        assert_eq!(
+            launcher.substitute_arg("--{SIDE}", &data).unwrap(),
+            "--client"
+        );
+        assert_eq!(
+            launcher.substitute_arg("{SIDE}:{MAPPINGS}", &data).unwrap(),
+            "client:/tmp/mappings.txt"
+        );
+        assert_eq!(
+            launcher
+                .substitute_arg("[net.minecraft:client:1.20.4:srg]", &data)
+                .unwrap(),
+            dir.path()
+                .join("libraries/net/minecraft/client/1.20.4/client-1.20.4-srg.jar")
+                .to_string_lossy()
+        );
+        assert!(matches!(
+            launcher.substitute_arg("{MISSING}", &data),
+            Err(Error::Installer(_))
+        ));
+    }
+
+    #[test]
+    fn data_values_are_resolved() {
+        let (dir, launcher, _) = get_launcher();
+        let mut archive = get_archive(
+            &dir.path().join("installer.jar"),
+            &[("data/client.lzma", "binpatches")],
+        );
+
+        assert_eq!(
+            launcher
+                .resolve_data_value(&mut archive, "'1a2b3c'")
+                .unwrap(),
+            "1a2b3c"
+        );
+        assert_eq!(
+            launcher
+                .resolve_data_value(&mut archive, "[de.oceanlabs.mcp:mcp_config:1.20.4@zip]")
+                .unwrap(),
+            dir.path()
+                .join("libraries/de/oceanlabs/mcp/mcp_config/1.20.4/mcp_config-1.20.4.zip")
+                .to_string_lossy()
+        );
+
+        let path = launcher
+            .resolve_data_value(&mut archive, "/data/client.lzma")
+            .unwrap();
+        assert_eq!(fs::read_to_string(path).unwrap(), "binpatches");
+    }
+
+    #[test]
+    fn bundled_libraries_stay_in_the_libraries_dir() {
+        let (dir, launcher, _) = get_launcher();
+        let mut archive = get_archive(
+            &dir.path().join("installer.jar"),
+            &[
+                (
+                    "maven/net/minecraftforge/forge/1.20.1-47.2.0/forge.jar",
+                    "forge",
+                ),
+                ("maven/../escaped.jar", "escaped"),
+                ("data/client.lzma", "binpatches"),
+            ],
+        );
+
+        launcher.extract_bundled_libraries(&mut archive).unwrap();
+
+        let libraries_dir = launcher.get_libraries_dir();
+        assert!(libraries_dir
+            .join("net/minecraftforge/forge/1.20.1-47.2.0/forge.jar")
+            .is_file());
+        assert!(!dir.path().join("escaped.jar").exists());
+        assert!(!libraries_dir.join("data").exists());
+    }
+}
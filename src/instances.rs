@@ -1,95 +1,173 @@
-use crate::{launchermeta, BASE_DIR};
-use anyhow::Result;
+use crate::{
+    cancel::CancellationToken,
+    error::{Error, Result},
+    launchermeta::Version,
+    loaders::{Loader, LoaderKind},
+    runtime, Launcher,
+};
 use serde::{Deserialize, Serialize};
 use std::fs::{self, create_dir_all, read_dir};
 use std::path::PathBuf;
-use url::Url;
+use tracing::{info, instrument};
 
 #[derive(Serialize, Deserialize)]
 pub struct Config {
     pub minecraft_version: String,
     pub version_type: String,
-    pub main_class: String,
+    /// overrides the version manifest mainClass, only set when a mod loader is installed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub main_class: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loader: Option<Loader>,
 }
 
-lazy_static! {
-    static ref INSTANCES_DIR: PathBuf = BASE_DIR.join("instances");
+impl Config {
+    /// returns the id of the version manifest to launch
+    pub fn get_version_id(&self) -> &str {
+        match &self.loader {
+            Some(loader) => &loader.version_id,
+            None => &self.minecraft_version,
+        }
+    }
 }
 
-pub fn get_instance_path(name: &str) -> Result<PathBuf> {
-    let path = INSTANCES_DIR.join(name);
-
-    Ok(path)
-}
-
-fn get_config_path(instance_name: &str) -> Result<PathBuf> {
-    let path = get_instance_path(instance_name)?
-        .join("config")
-        .with_extension("json");
-
-    Ok(path)
-}
-
-pub fn read_config(instance_name: &str) -> Result<Config> {
-    let path = get_config_path(instance_name)?;
-    let data = fs::read_to_string(path)?;
-    let config = serde_json::from_str(&data)?;
-
-    Ok(config)
-}
-
-fn write_config(instance_name: &str, config: &Config) -> Result<()> {
-    let path = get_config_path(instance_name)?;
-    let data = serde_json::to_string_pretty(config)?;
-    fs::write(path, data)?;
-
-    Ok(())
-}
-
-pub fn get_instance_list() -> Result<Vec<String>> {
-    let instance_list = read_dir(INSTANCES_DIR.as_path())?
-        .filter_map(|entry| entry.ok())
-        .map(|entry| entry.file_name())
-        .filter_map(|entry| entry.into_string().ok())
-        .filter(|entry| entry.ne(".DS_Store"))
-        .collect::<Vec<String>>();
-
-    Ok(instance_list)
-}
-
-pub async fn new_instance(
-    name: &str,
-    minecraft_version: &str,
-    minecraft_version_manifest_url: &Url,
-) -> Result<()> {
-    let instance_dir = get_instance_path(name)?;
-    create_dir_all(&instance_dir)?;
-
-    let config = Config {
-        minecraft_version: minecraft_version.to_owned(),
-        version_type: String::from("Vanilla"),
-        main_class: String::from("net.minecraft.launchwrapper.Launch"),
-    };
-    write_config(name, &config)?;
-
-    launchermeta::download_minecraft_manifest(minecraft_version, minecraft_version_manifest_url)?;
-
-    Ok(())
-}
-
-pub fn remove_instance(name: &str) -> Result<()> {
-    let instance_dir = get_instance_path(name)?;
-
-    fs::remove_dir_all(instance_dir)?;
-
-    Ok(())
-}
-
-pub fn rename_instance(old_name: &str, new_name: &str) -> Result<()> {
-    let old_instance_dir = get_instance_path(old_name)?;
-    let new_instance_dir = old_instance_dir.parent().unwrap().join(new_name);
-
-    fs::rename(old_instance_dir, new_instance_dir)?;
-
-    Ok(())
+/// main class that older versions of this crate wrote to every instance config
+const LEGACY_DEFAULT_MAIN_CLASS: &str = "net.minecraft.launchwrapper.Launch";
+
+impl Launcher {
+    fn get_instances_dir(&self) -> PathBuf {
+        self.get_root_dir().join("instances")
+    }
+
+    pub fn get_instance_path(&self, name: &str) -> Result<PathBuf> {
+        let path = self.get_instances_dir().join(name);
+
+        Ok(path)
+    }
+
+    fn get_instance_config_path(&self, instance_name: &str) -> Result<PathBuf> {
+        let path = self
+            .get_instance_path(instance_name)?
+            .join("config")
+            .with_extension("json");
+
+        Ok(path)
+    }
+
+    pub fn read_instance_config(&self, instance_name: &str) -> Result<Config> {
+        let path = self.get_instance_config_path(instance_name)?;
+        if !path.is_file() {
+            return Err(Error::InstanceNotFound(instance_name.to_string()));
+        }
+        let data = fs::read_to_string(path)?;
+        let mut config: Config = serde_json::from_str(&data)?;
+
+        if config.version_type.eq("Vanilla")
+            && config.main_class.as_deref() == Some(LEGACY_DEFAULT_MAIN_CLASS)
+        {
+            config.main_class = None;
+            self.write_instance_config(instance_name, &config)?;
+        }
+
+        Ok(config)
+    }
+
+    fn write_instance_config(&self, instance_name: &str, config: &Config) -> Result<()> {
+        let path = self.get_instance_config_path(instance_name)?;
+        let data = serde_json::to_string_pretty(config)?;
+        fs::write(path, data)?;
+
+        Ok(())
+    }
+
+    pub fn get_instance_list(&self) -> Result<Vec<String>> {
+        let instances_dir = self.get_instances_dir();
+        if !instances_dir.is_dir() {
+            return Ok(Vec::new());
+        }
+
+        let instance_list = read_dir(instances_dir)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name())
+            .filter_map(|entry| entry.into_string().ok())
+            .filter(|entry| entry.ne(".DS_Store"))
+            .collect::<Vec<String>>();
+
+        Ok(instance_list)
+    }
+
+    #[instrument(skip_all, fields(name = %name, minecraft_version = %version.id))]
+    pub(crate) async fn new_instance_async(
+        &self,
+        name: &str,
+        version: &Version,
+        loader: Option<(LoaderKind, &str)>,
+        cancel: &CancellationToken,
+    ) -> Result<()> {
+        let minecraft_version = &version.id;
+        self.download_minecraft_manifest_async(version, cancel)
+            .await?;
+
+        let (version_type, loader) = match loader {
+            Some((kind, loader_version)) => (
+                kind.get_name(),
+                Some(
+                    self.install_loader_async(kind, minecraft_version, loader_version, cancel)
+                        .await?,
+                ),
+            ),
+            None => ("Vanilla", None),
+        };
+
+        let instance_dir = self.get_instance_path(name)?;
+        create_dir_all(&instance_dir)?;
+
+        let config = Config {
+            minecraft_version: minecraft_version.to_owned(),
+            version_type: version_type.to_string(),
+            main_class: None,
+            loader,
+        };
+        self.write_instance_config(name, &config)?;
+        info!("created instance {}", name);
+
+        Ok(())
+    }
+
+    /// version is an entry returned by get_minecraft_versions,
+    /// loader is the mod loader to install with its version, None for vanilla
+    pub fn new_instance(
+        &self,
+        name: &str,
+        version: &Version,
+        loader: Option<(LoaderKind, &str)>,
+        cancel: &CancellationToken,
+    ) -> Result<()> {
+        runtime::block_on(self.new_instance_async(name, version, loader, cancel))
+    }
+
+    pub fn remove_instance(&self, name: &str) -> Result<()> {
+        let instance_dir = self.get_instance_path(name)?;
+        if !instance_dir.is_dir() {
+            return Err(Error::InstanceNotFound(name.to_string()));
+        }
+
+        fs::remove_dir_all(instance_dir)?;
+        info!("removed instance {}", name);
+
+        Ok(())
+    }
+
+    pub fn rename_instance(&self, old_name: &str, new_name: &str) -> Result<()> {
+        let old_instance_dir = self.get_instance_path(old_name)?;
+        if !old_instance_dir.is_dir() {
+            return Err(Error::InstanceNotFound(old_name.to_string()));
+        }
+        let new_instance_dir = old_instance_dir.parent().unwrap().join(new_name);
+
+        fs::rename(old_instance_dir, new_instance_dir)?;
+        info!("renamed instance {} to {}", old_name, new_name);
+
+        Ok(())
+    }
 }
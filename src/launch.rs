@@ -1,86 +1,213 @@
-use std::process::Command;
+use std::{
+    collections::HashSet,
+    env,
+    path::PathBuf,
+    process::{Child, Command},
+};
 
-use anyhow::Result;
+use tracing::{info, info_span, instrument, Instrument};
 
 use crate::{
-    assets::{self, ASSETS_DIR},
-    config, instances, launchermeta, libraries,
-    profile::UserProfile,
+    accounts::Session,
+    arguments::{Variable, Variables},
+    cancel::CancellationToken,
+    error::{Error, Result},
+    launchermeta::{self, Artifact},
+    progress::{Event, Phase, Progress},
+    rules::Environment,
+    runtime, Launcher,
 };
 
-fn update_last_launched(instance_name: &str) -> Result<()> {
-    let mut config = config::read()?;
-    config.last_launched_instance = instance_name.to_string();
-    config::write(&config)?;
+#[cfg(windows)]
+const CLASSPATH_SEPARATOR: &str = ";";
+#[cfg(not(windows))]
+const CLASSPATH_SEPARATOR: &str = ":";
 
-    Ok(())
+/// Forge and NeoForge 1.17+ load the game from their own patched jars and tell their
+/// bootstrap launcher to skip `${version_name}.jar`, which doesn't match the name
+/// of the shared client jar, so it has to stay off the classpath instead
+fn is_client_jar_ignored(jvm_args: &[String]) -> bool {
+    jvm_args.iter().any(|arg| {
+        arg.starts_with("-DignoreList=")
+            && arg
+                .split(',')
+                .any(|entry| entry.ends_with("${version_name}.jar"))
+    })
 }
 
-fn get_classpath(_minecraft_meta: &launchermeta::MinecraftMeta) -> String {
-    "TODO".to_string()
-}
+impl Launcher {
+    fn update_last_launched(&self, instance_name: &str) -> Result<()> {
+        let mut config = self.get_config();
+        config.last_launched_instance = instance_name.to_string();
+        self.set_config(config)?;
+
+        Ok(())
+    }
+
+    fn get_classpath(
+        &self,
+        minecraft_meta: &launchermeta::MinecraftMeta,
+        artifacts: &[Artifact],
+        jvm_args: &[String],
+    ) -> Result<String> {
+        let mut seen = HashSet::new();
+        let mut paths: Vec<PathBuf> = artifacts
+            .iter()
+            .map(|artifact| self.get_artifact_path(artifact))
+            .filter(|path| seen.insert(path.clone()))
+            .collect();
+
+        if !is_client_jar_ignored(jvm_args) {
+            paths.push(self.get_client_jar_path(minecraft_meta));
+        }
+
+        let class_path = env::join_paths(paths)?
+            .into_string()
+            .map_err(|class_path| Error::InvalidPath(class_path.into()))?;
+
+        Ok(class_path)
+    }
+
+    /// prepares the instance and returns the java command starting it
+    #[instrument(skip_all, fields(instance = %instance_name))]
+    pub(crate) async fn get_launch_command(
+        &self,
+        instance_name: &str,
+        session: &Session,
+        progress: &dyn Progress,
+        cancel: &CancellationToken,
+    ) -> Result<Command> {
+        self.update_last_launched(instance_name)?;
+
+        let java_path = self.get_config().java.path;
+
+        let config = self.read_instance_config(instance_name)?;
+        let minecraft_meta = self.read_minecraft_manifest(config.get_version_id())?;
+        let env = Environment::current();
+        info!("launching version {}", config.get_version_id());
+
+        let instance_dir = self.get_instance_path(instance_name)?;
+
+        progress.report(Event::PhaseStarted(Phase::Assets));
+        let game_assets = self
+            .download_assets_async(&minecraft_meta.asset_index, &instance_dir, progress, cancel)
+            .instrument(info_span!("assets"))
+            .await?;
+
+        progress.report(Event::PhaseStarted(Phase::Libraries));
+        let (artifacts, native_artifacts) = self
+            .download_libraries_async(&minecraft_meta, &env, progress, cancel)
+            .instrument(info_span!("libraries"))
+            .await?;
 
-pub fn launch(instance_name: &str, mc_profile: &UserProfile, mc_access_token: &str) -> Result<()> {
-    update_last_launched(instance_name)?;
-
-    let java_path = config::read()?.java.path;
-
-    let config = instances::read_config(instance_name)?;
-    let minecraft_meta = launchermeta::read_minecraft_manifest(&config.minecraft_version)?;
-
-    assets::download_assets(&minecraft_meta.asset_index)?;
-    let (artifacts, native_artifacts) = libraries::download_libraries(&minecraft_meta)?;
-    let natives_dir = libraries::extract_natives(&native_artifacts)?.as_path().to_str().unwrap().to_owned();
-
-    let class_path = get_classpath(&minecraft_meta);
-
-    // parse jvm args
-    let mut jvm_args: Vec<String> = Vec::new();
-    for arg in launchermeta::get_jvm_args(&minecraft_meta) {
-        let final_arg = match arg.as_str() {
-            "-Djava.library.path=${natives_directory}" => {
-                format!("-Djava.library.path={}", &natives_dir)
-            },
-            "-Dminecraft.launcher.brand=${launcher_name}" => {
-                format!("-Dminecraft.launcher.brand={}", env!("CARGO_PKG_NAME"))
-            }
-            "-Dminecraft.launcher.version=${launcher_version}" => {
-                format!("-Dminecraft.launcher.version={}", env!("CARGO_PKG_VERSION"))
-            }
-            "${classpath}" => class_path.clone(),
-            _ => arg,
-        };
-        jvm_args.push(final_arg);
+        progress.report(Event::PhaseStarted(Phase::Natives));
+        let natives_dir = self
+            .extract_natives_async(native_artifacts, cancel)
+            .instrument(info_span!("natives"))
+            .await?;
+
+        let jvm_args = launchermeta::get_jvm_args(&minecraft_meta, &env);
+        let class_path = self.get_classpath(&minecraft_meta, &artifacts, &jvm_args)?;
+
+        let mut variables = Variables::new();
+        variables
+            .set(Variable::AuthPlayerName, &session.profile.name)
+            .set(Variable::AuthUuid, &session.profile.id)
+            .set(Variable::AuthAccessToken, &session.access_token)
+            .set(Variable::AuthSession, &session.access_token)
+            .set(Variable::AuthXuid, "0")
+            .set(Variable::UserType, &session.user_type)
+            .set(Variable::UserProperties, "{}")
+            .set(
+                Variable::ClientId,
+                format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+            )
+            .set(Variable::VersionName, config.get_version_id())
+            .set(Variable::VersionType, &config.version_type)
+            .set(Variable::GameDirectory, ".")
+            .set(
+                Variable::AssetsRoot,
+                self.get_assets_dir().to_string_lossy(),
+            )
+            .set(Variable::AssetsIndexName, &minecraft_meta.asset_index.id)
+            .set(Variable::GameAssets, game_assets.to_string_lossy())
+            .set(
+                Variable::LibraryDirectory,
+                self.get_libraries_dir().to_string_lossy(),
+            )
+            .set(Variable::NativesDirectory, natives_dir.to_string_lossy())
+            .set(Variable::Classpath, class_path)
+            .set(Variable::ClasspathSeparator, CLASSPATH_SEPARATOR)
+            .set(Variable::LauncherName, env!("CARGO_PKG_NAME"))
+            .set(Variable::LauncherVersion, env!("CARGO_PKG_VERSION"));
+
+        let mut jvm_args = variables.substitute(&jvm_args)?;
+        let mut game_args =
+            variables.substitute(&launchermeta::get_game_args(&minecraft_meta, &env))?;
+
+        let main_class = config.main_class.unwrap_or(minecraft_meta.main_class);
+        // the arguments carry the access token, only the main class is logged
+        info!("starting {} with {}", main_class, java_path);
+
+        let mut final_args = Vec::new();
+        final_args.append(&mut jvm_args);
+        final_args.push(main_class);
+        final_args.append(&mut game_args);
+
+        cancel.check()?;
+        progress.report(Event::PhaseStarted(Phase::Starting));
+        let mut command = Command::new(java_path);
+        command.args(final_args).current_dir(instance_dir);
+
+        Ok(command)
     }
 
-    // parse game args
-    let mut game_args: Vec<String> = Vec::new();
-    for arg in launchermeta::get_game_args(&minecraft_meta) {
-        let final_arg = match arg.as_str() {
-            "${auth_player_name}" => mc_profile.name.clone(),
-            "${version_name}" => config.minecraft_version.clone(),
-            "${game_directory}" => ".".to_string(),
-            "${assets_root}" => ASSETS_DIR.as_path().to_str().unwrap().to_owned(),
-            "${assets_index_name}" => minecraft_meta.asset_index.id.clone(),
-            "${auth_uuid}" => mc_profile.id.clone(),
-            "${auth_access_token}" => mc_access_token.to_string(),
-            "${clientid}" => format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
-            "${user_type}" => "mojang".to_string(),
-            "${version_type}" => config.version_type.clone(),
-            _ => arg,
-        };
-        game_args.push(final_arg);
+    /// returns the game process, wait on it to know when the game exits
+    pub fn launch(
+        &self,
+        instance_name: &str,
+        session: &Session,
+        progress: &dyn Progress,
+        cancel: &CancellationToken,
+    ) -> Result<Child> {
+        let mut command =
+            runtime::block_on(self.get_launch_command(instance_name, session, progress, cancel))?;
+        let child = command.spawn()?;
+
+        Ok(child)
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let mut final_args = Vec::new();
-    final_args.append(&mut jvm_args);
-    final_args.push(config.main_class);
-    final_args.append(&mut game_args);
+    fn get_args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
 
-    Command::new(java_path)
-        .args(final_args)
-        .current_dir(instances::get_instance_path(instance_name)?)
-        .spawn()?;
+    #[test]
+    fn forge_ignores_the_client_jar() {
+        let args = get_args(&[
+            "-Djava.library.path=${natives_directory}",
+            "-DignoreList=bootstraplauncher,securejarhandler,client-extra,fmlcore,${version_name}.jar",
+            "-cp",
+            "${classpath}",
+        ]);
 
-    Ok(())
+        assert!(is_client_jar_ignored(&args));
+    }
+
+    #[test]
+    fn vanilla_keeps_the_client_jar() {
+        let args = get_args(&[
+            "-Djava.library.path=${natives_directory}",
+            "-cp",
+            "${classpath}",
+        ]);
+        assert!(!is_client_jar_ignored(&args));
+
+        let args = get_args(&["-DignoreList=bootstraplauncher,securejarhandler"]);
+        assert!(!is_client_jar_ignored(&args));
+    }
 }
@@ -0,0 +1,125 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use directories::ProjectDirs;
+
+use crate::{
+    config::{self, Config},
+    error::{Error, Result},
+    http::{self, Transport},
+};
+
+/// Owns a root directory holding the launcher config, accounts, instances, assets and libraries.
+/// Launchers with different roots are independent and can be used in the same process,
+/// clones share the config and transport.
+#[derive(Clone)]
+pub struct Launcher {
+    root_dir: PathBuf,
+    config: Arc<RwLock<Config>>,
+    transport: Arc<RwLock<TransportSlot>>,
+}
+
+struct TransportSlot {
+    transport: Arc<dyn Transport>,
+    /// set by set_transport, the config can't rebuild an injected transport
+    custom: bool,
+}
+
+fn get_config_path(root_dir: &Path) -> PathBuf {
+    root_dir.join("config").with_extension("json")
+}
+
+impl Launcher {
+    /// uses root_dir with config, the network client is built from config.http
+    pub fn new(root_dir: impl Into<PathBuf>, config: Config) -> Result<Self> {
+        let root_dir = root_dir.into();
+        fs::create_dir_all(&root_dir)?;
+
+        let transport = http::get_default_transport(&config.http)?;
+
+        Ok(Self {
+            root_dir,
+            config: Arc::new(RwLock::new(config)),
+            transport: Arc::new(RwLock::new(TransportSlot {
+                transport,
+                custom: false,
+            })),
+        })
+    }
+
+    /// uses root_dir with the config stored in it, writing the default one if missing
+    pub fn open(root_dir: impl Into<PathBuf>) -> Result<Self> {
+        let root_dir = root_dir.into();
+        fs::create_dir_all(&root_dir)?;
+
+        let config = config::read(&get_config_path(&root_dir))?;
+
+        Self::new(root_dir, config)
+    }
+
+    /// returns the per-user data directory of the standalone launcher
+    pub fn get_default_root_dir() -> Result<PathBuf> {
+        let dirs = ProjectDirs::from("eu", "mq1", "runmc").ok_or(Error::NoHomeDir)?;
+
+        Ok(dirs.data_dir().to_path_buf())
+    }
+
+    pub fn get_root_dir(&self) -> &Path {
+        &self.root_dir
+    }
+
+    pub fn get_config(&self) -> Config {
+        self.config.read().unwrap().clone()
+    }
+
+    /// replaces and saves the config, the network client is rebuilt when the http settings
+    /// change unless it was replaced with set_transport
+    pub fn set_config(&self, config: Config) -> Result<()> {
+        let mut slot = self.transport.write().unwrap();
+        let transport = match !slot.custom && config.http != self.get_config().http {
+            true => Some(http::get_default_transport(&config.http)?),
+            false => None,
+        };
+
+        config::write(&get_config_path(&self.root_dir), &config)?;
+        if let Some(transport) = transport {
+            slot.transport = transport;
+        }
+        *self.config.write().unwrap() = config;
+
+        Ok(())
+    }
+
+    /// replaces the network client, e.g. with a MemoryTransport in tests
+    pub fn set_transport(&self, transport: Arc<dyn Transport>) {
+        *self.transport.write().unwrap() = TransportSlot {
+            transport,
+            custom: true,
+        };
+    }
+
+    pub(crate) fn get_transport(&self) -> Arc<dyn Transport> {
+        self.transport.read().unwrap().transport.clone()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::http::MemoryTransport;
+
+    /// returns a launcher in a temporary root, answering requests from the returned transport
+    pub(crate) fn get_launcher() -> (TempDir, Launcher, Arc<MemoryTransport>) {
+        let dir = tempfile::tempdir().unwrap();
+        let launcher = Launcher::new(dir.path(), config::get_default_config()).unwrap();
+        let transport = Arc::new(MemoryTransport::new());
+        launcher.set_transport(transport.clone());
+
+        (dir, launcher, transport)
+    }
+}
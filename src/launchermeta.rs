@@ -1,13 +1,18 @@
-use crate::{download_file, BASE_DIR};
-use anyhow::Result;
+use crate::{
+    cancel::CancellationToken,
+    error::{Error, Result},
+    progress::NoProgress,
+    rules::{self, Environment},
+    runtime, Launcher,
+};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use std::collections::HashMap;
-use std::fs::File;
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
 use std::path::PathBuf;
 use url::Url;
 
-const VERSION_MANIFEST_URL: &str = "https://launchermeta.mojang.com/mc/game/version_manifest.json";
-
 #[derive(Deserialize)]
 #[serde(untagged)]
 pub enum ArgumentValue {
@@ -31,11 +36,13 @@ pub struct Arguments {
     pub jvm: Vec<Argument>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Version {
     pub id: String,
     pub r#type: String,
     pub url: Url,
+    /// sha1 of the version JSON, missing from the v1 version manifest
+    pub sha1: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -47,11 +54,15 @@ struct VersionManifest {
 pub struct AssetIndexMeta {
     pub id: String,
     pub url: Url,
+    pub sha1: Option<String>,
+    pub size: Option<u64>,
 }
 
 #[derive(Deserialize)]
 pub struct URLObject {
     pub url: Url,
+    pub sha1: Option<String>,
+    pub size: Option<u64>,
 }
 
 #[derive(Deserialize)]
@@ -62,30 +73,69 @@ pub struct Downloads {
 #[derive(Clone, Deserialize)]
 pub struct Artifact {
     pub path: String,
-    pub url: Url,
+    /// None when the artifact is not downloadable (e.g. produced by the forge installer)
+    #[serde(deserialize_with = "deserialize_optional_url")]
+    pub url: Option<Url>,
+    pub sha1: Option<String>,
+    pub size: Option<u64>,
+}
+
+/// forge writes "url": "" for artifacts that can't be downloaded
+fn deserialize_optional_url<'de, D>(deserializer: D) -> Result<Option<Url>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let url = String::deserialize(deserializer)?;
+    if url.is_empty() {
+        return Ok(None);
+    }
+
+    Url::parse(&url).map(Some).map_err(serde::de::Error::custom)
 }
 
 #[derive(Deserialize)]
 pub struct LibDownloads {
-    pub artifact: Artifact,
+    pub artifact: Option<Artifact>,
     pub classifiers: Option<HashMap<String, Artifact>>,
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JavaVersion {
+    pub component: String,
+    pub major_version: u32,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleAction {
+    Allow,
+    Disallow,
+}
+
 #[derive(Deserialize)]
 pub struct Os {
-    pub name: String,
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub arch: Option<String>,
 }
 
 #[derive(Deserialize)]
 pub struct Rule {
-    pub action: String,
+    pub action: RuleAction,
     pub os: Option<Os>,
+    pub features: Option<HashMap<String, bool>>,
 }
 
 #[derive(Deserialize)]
 pub struct Library {
-    pub downloads: LibDownloads,
+    /// missing on libraries coming from mod loader profiles
+    pub downloads: Option<LibDownloads>,
     pub name: String,
+    /// maven repository base url, used when downloads is missing
+    pub url: Option<Url>,
+    pub sha1: Option<String>,
+    pub size: Option<u64>,
     pub natives: Option<HashMap<String, String>>,
     pub rules: Option<Vec<Rule>>,
 }
@@ -93,100 +143,456 @@ pub struct Library {
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct MinecraftMeta {
-    pub arguments: Arguments,
+    pub arguments: Option<Arguments>,
+    /// flat argument string used by versions before 1.13
+    pub minecraft_arguments: Option<String>,
     pub id: String,
+    pub main_class: String,
     pub asset_index: AssetIndexMeta,
     pub downloads: Downloads,
     pub libraries: Vec<Library>,
+    pub java_version: Option<JavaVersion>,
+    /// id of the version whose client jar is used, set when inheriting
+    pub jar: Option<String>,
 }
 
-lazy_static! {
-    static ref MINECRAFT_MANIFESTS_DIR: PathBuf = BASE_DIR.join("meta").join("net.minecraft");
+impl MinecraftMeta {
+    /// returns the id of the version providing the client jar
+    pub fn get_jar_id(&self) -> &str {
+        self.jar.as_deref().unwrap_or(&self.id)
+    }
 }
 
-fn get_minecraft_manifest_path(minecraft_version: &str) -> PathBuf {
-    let minecraft_version_manifest_path = MINECRAFT_MANIFESTS_DIR
-        .join(minecraft_version)
-        .with_extension("json");
+/// returns group:artifact[:classifier], dropping the version
+fn get_library_key(library: &Value) -> Option<String> {
+    let name = library.get("name")?.as_str()?;
+    let mut parts = name.split(':');
+    let group = parts.next()?;
+    let artifact = parts.next()?;
+    let classifier = parts.nth(1);
+
+    match classifier {
+        Some(classifier) => Some(format!("{group}:{artifact}:{classifier}")),
+        None => Some(format!("{group}:{artifact}")),
+    }
+}
 
-    minecraft_version_manifest_path
+fn merge_libraries(parent: Value, child: Value) -> Value {
+    let child = match child {
+        Value::Array(libraries) => libraries,
+        _ => return parent,
+    };
+    let parent = match parent {
+        Value::Array(libraries) => libraries,
+        _ => return Value::Array(child),
+    };
+
+    let child_keys: Vec<String> = child.iter().filter_map(get_library_key).collect();
+
+    let mut libraries = child;
+    libraries.extend(
+        parent.into_iter().filter(|library| {
+            get_library_key(library).is_none_or(|key| !child_keys.contains(&key))
+        }),
+    );
+
+    Value::Array(libraries)
 }
 
-pub fn get_minecraft_versions() -> Result<Vec<Version>> {
-    let resp: VersionManifest = ureq::get(VERSION_MANIFEST_URL).call()?.into_json()?;
+fn merge_arguments(parent: Value, child: Value) -> Value {
+    let (mut parent, child) = match (parent, child) {
+        (Value::Object(parent), Value::Object(child)) => (parent, child),
+        (_, child) => return child,
+    };
+
+    for (key, value) in child {
+        match (parent.get_mut(&key), value) {
+            (Some(Value::Array(parent_args)), Value::Array(mut child_args)) => {
+                parent_args.append(&mut child_args);
+            }
+            (_, value) => {
+                parent.insert(key, value);
+            }
+        }
+    }
 
-    Ok(resp.versions)
+    Value::Object(parent)
 }
 
-pub fn download_minecraft_manifest(
-    minecraft_version: &str,
-    minecraft_version_manifest_url: &Url,
-) -> Result<()> {
-    let minecraft_version_manifest_path = get_minecraft_manifest_path(minecraft_version);
+/// Layers a child version JSON on top of its parent:
+/// libraries are overridden by group:artifact, arguments are appended
+/// and every other field of the child replaces the parent one.
+fn merge_manifests(
+    mut parent: Map<String, Value>,
+    child: Map<String, Value>,
+) -> Map<String, Value> {
+    if !child.contains_key("jar") && !parent.contains_key("jar") {
+        if let Some(id) = parent.get("id").cloned() {
+            parent.insert("jar".to_string(), id);
+        }
+    }
 
-    // check if it was already downloaded
-    if !minecraft_version_manifest_path.is_file() {
-        download_file(
-            minecraft_version_manifest_url,
-            &minecraft_version_manifest_path,
-        )?;
+    for (key, value) in child {
+        let merged = match (key.as_str(), parent.remove(&key)) {
+            ("inheritsFrom", _) => continue,
+            ("libraries", Some(parent_value)) => merge_libraries(parent_value, value),
+            ("arguments", Some(parent_value)) => merge_arguments(parent_value, value),
+            _ => value,
+        };
+        parent.insert(key, merged);
     }
 
-    Ok(())
+    parent
 }
 
-pub fn read_minecraft_manifest(minecraft_version: &str) -> Result<MinecraftMeta> {
-    let minecraft_version_manifest_path = get_minecraft_manifest_path(minecraft_version);
-    let file = File::open(minecraft_version_manifest_path)?;
-    let config = serde_json::from_reader(file)?;
+impl Launcher {
+    fn get_minecraft_manifests_dir(&self) -> PathBuf {
+        self.get_root_dir().join("meta").join("net.minecraft")
+    }
 
-    Ok(config)
-}
+    fn get_minecraft_manifest_path(&self, minecraft_version: &str) -> PathBuf {
+        // not with_extension, it would replace the last part of ids like 1.20.4
+        self.get_minecraft_manifests_dir()
+            .join(format!("{minecraft_version}.json"))
+    }
+
+    pub(crate) async fn get_minecraft_versions_async(&self) -> Result<Vec<Version>> {
+        let resp: VersionManifest = self
+            .get_json(&self.get_config().endpoints.version_manifest)
+            .await?;
+
+        Ok(resp.versions)
+    }
+
+    pub fn get_minecraft_versions(&self) -> Result<Vec<Version>> {
+        runtime::block_on(self.get_minecraft_versions_async())
+    }
+
+    pub(crate) async fn download_minecraft_manifest_async(
+        &self,
+        version: &Version,
+        cancel: &CancellationToken,
+    ) -> Result<()> {
+        let minecraft_version_manifest_path = self.get_minecraft_manifest_path(&version.id);
+
+        // an existing file is kept when it matches the sha1
+        self.download_file_async(
+            &version.url,
+            &minecraft_version_manifest_path,
+            version.sha1.as_deref(),
+            None,
+            &self.get_download_options(cancel),
+            &NoProgress,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// downloads the version JSON of an entry returned by get_minecraft_versions
+    pub fn download_minecraft_manifest(
+        &self,
+        version: &Version,
+        cancel: &CancellationToken,
+    ) -> Result<()> {
+        runtime::block_on(self.download_minecraft_manifest_async(version, cancel))
+    }
+
+    /// stores a version JSON fetched from elsewhere (e.g. a mod loader profile),
+    /// returns its id
+    pub fn write_minecraft_manifest(&self, manifest: &Value) -> Result<String> {
+        let id = manifest
+            .get("id")
+            .and_then(Value::as_str)
+            .ok_or_else(|| Error::InvalidManifest("version manifest has no id".to_string()))?;
+
+        let minecraft_version_manifest_path = self.get_minecraft_manifest_path(id);
+        let dir = minecraft_version_manifest_path
+            .parent()
+            .ok_or_else(|| Error::InvalidPath(minecraft_version_manifest_path.clone()))?;
+        fs::create_dir_all(dir)?;
+
+        let file = File::create(minecraft_version_manifest_path)?;
+        serde_json::to_writer_pretty(BufWriter::new(file), manifest)?;
+
+        Ok(id.to_string())
+    }
+
+    fn read_manifest_value(&self, minecraft_version: &str) -> Result<Map<String, Value>> {
+        let minecraft_version_manifest_path = self.get_minecraft_manifest_path(minecraft_version);
+        let file = File::open(minecraft_version_manifest_path).map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => Error::VersionNotFound(minecraft_version.to_string()),
+            _ => Error::Io(e),
+        })?;
+
+        match serde_json::from_reader(file)? {
+            Value::Object(manifest) => Ok(manifest),
+            _ => Err(Error::InvalidManifest(format!(
+                "version manifest {minecraft_version} is not an object"
+            ))),
+        }
+    }
+
+    fn resolve_manifest(
+        &self,
+        minecraft_version: &str,
+        chain: &mut Vec<String>,
+    ) -> Result<Map<String, Value>> {
+        if chain.iter().any(|id| id == minecraft_version) {
+            return Err(Error::InvalidManifest(format!(
+                "circular inheritsFrom chain: {} -> {}",
+                chain.join(" -> "),
+                minecraft_version
+            )));
+        }
+        chain.push(minecraft_version.to_string());
+
+        let manifest = self.read_manifest_value(minecraft_version)?;
 
-// TODO refactor these two methods
+        let parent_version = match manifest.get("inheritsFrom") {
+            Some(Value::String(parent_version)) => parent_version.clone(),
+            _ => return Ok(manifest),
+        };
 
-// TODO parse rules
-pub fn get_jvm_args(minecraft_meta: &MinecraftMeta) -> Vec<String> {
+        let parent = self.resolve_manifest(&parent_version, chain)?;
+
+        Ok(merge_manifests(parent, manifest))
+    }
+
+    /// Reads a version JSON, resolving its inheritsFrom chain into one effective manifest
+    pub fn read_minecraft_manifest(&self, minecraft_version: &str) -> Result<MinecraftMeta> {
+        let manifest = self.resolve_manifest(minecraft_version, &mut Vec::new())?;
+        let config = serde_json::from_value(Value::Object(manifest))?;
+
+        Ok(config)
+    }
+}
+
+fn collect_args(args: &[Argument], env: &Environment) -> Vec<String> {
     let mut final_args = Vec::new();
 
-    for arg in &minecraft_meta.arguments.jvm {
+    for arg in args {
         match arg {
             Argument::Simple(argument) => {
                 final_args.push(argument.to_owned());
             }
-            Argument::Explicit { rules, value } => match value {
-                crate::launchermeta::ArgumentValue::One(argument) => {
-                    final_args.push(argument.to_owned());
+            Argument::Explicit { rules, value } => {
+                if !rules::is_allowed(rules, env) {
+                    continue;
                 }
-                crate::launchermeta::ArgumentValue::Multiple(arguments) => {
-                    final_args.append(&mut arguments.to_owned());
+
+                match value {
+                    ArgumentValue::One(argument) => {
+                        final_args.push(argument.to_owned());
+                    }
+                    ArgumentValue::Multiple(arguments) => {
+                        final_args.append(&mut arguments.to_owned());
+                    }
                 }
-            },
+            }
         }
     }
 
     final_args
 }
 
-// TODO parse rules
-pub fn get_game_args(minecraft_meta: &MinecraftMeta) -> Vec<String> {
+/// jvm arguments the vanilla launcher adds to versions without an arguments object
+fn get_legacy_jvm_args(env: &Environment) -> Vec<String> {
     let mut final_args = Vec::new();
 
-    for arg in &minecraft_meta.arguments.game {
-        match arg {
-            Argument::Simple(argument) => {
-                final_args.push(argument.to_owned());
-            }
-            Argument::Explicit { rules, value } => match value {
-                crate::launchermeta::ArgumentValue::One(argument) => {
-                    final_args.push(argument.to_owned());
-                }
-                crate::launchermeta::ArgumentValue::Multiple(arguments) => {
-                    final_args.append(&mut arguments.to_owned());
-                }
-            },
-        }
+    if env.os_name.eq("osx") {
+        final_args.push("-XstartOnFirstThread".to_string());
     }
 
+    final_args.append(&mut vec![
+        "-Djava.library.path=${natives_directory}".to_string(),
+        "-Dminecraft.launcher.brand=${launcher_name}".to_string(),
+        "-Dminecraft.launcher.version=${launcher_version}".to_string(),
+        "-cp".to_string(),
+        "${classpath}".to_string(),
+    ]);
+
     final_args
 }
+
+pub fn get_jvm_args(minecraft_meta: &MinecraftMeta, env: &Environment) -> Vec<String> {
+    match &minecraft_meta.arguments {
+        Some(arguments) => collect_args(&arguments.jvm, env),
+        None => get_legacy_jvm_args(env),
+    }
+}
+
+pub fn get_game_args(minecraft_meta: &MinecraftMeta, env: &Environment) -> Vec<String> {
+    match (
+        &minecraft_meta.arguments,
+        &minecraft_meta.minecraft_arguments,
+    ) {
+        (Some(arguments), _) => collect_args(&arguments.game, env),
+        (None, Some(minecraft_arguments)) => minecraft_arguments
+            .split_whitespace()
+            .map(String::from)
+            .collect(),
+        (None, None) => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::launcher::tests::get_launcher;
+
+    fn get_object(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(map) => map,
+            _ => panic!("expected an object"),
+        }
+    }
+
+    #[test]
+    fn child_library_replaces_parent() {
+        let parent = json!([
+            { "name": "org.ow2.asm:asm:9.3" },
+            { "name": "com.mojang:brigadier:1.0.18" }
+        ]);
+        let child = json!([
+            { "name": "org.ow2.asm:asm:9.6" },
+            { "name": "net.fabricmc:fabric-loader:0.15.0" }
+        ]);
+
+        assert_eq!(
+            merge_libraries(parent, child),
+            json!([
+                { "name": "org.ow2.asm:asm:9.6" },
+                { "name": "net.fabricmc:fabric-loader:0.15.0" },
+                { "name": "com.mojang:brigadier:1.0.18" }
+            ])
+        );
+    }
+
+    #[test]
+    fn classifiers_are_different_libraries() {
+        let parent = json!([{ "name": "org.lwjgl:lwjgl:3.3.1:natives-linux" }]);
+        let child = json!([{ "name": "org.lwjgl:lwjgl:3.3.2" }]);
+
+        assert_eq!(
+            merge_libraries(parent, child),
+            json!([
+                { "name": "org.lwjgl:lwjgl:3.3.2" },
+                { "name": "org.lwjgl:lwjgl:3.3.1:natives-linux" }
+            ])
+        );
+    }
+
+    #[test]
+    fn arguments_are_appended() {
+        let parent = get_object(json!({
+            "id": "1.20.4",
+            "arguments": { "game": ["--username", "${auth_player_name}"], "jvm": ["-cp"] }
+        }));
+        let child = get_object(json!({
+            "id": "fabric-loader-0.15.0-1.20.4",
+            "inheritsFrom": "1.20.4",
+            "arguments": { "jvm": ["-DFabricMcEmu= net.minecraft.client.main.Main "] }
+        }));
+
+        let merged = merge_manifests(parent, child);
+
+        assert_eq!(
+            merged["arguments"],
+            json!({
+                "game": ["--username", "${auth_player_name}"],
+                "jvm": ["-cp", "-DFabricMcEmu= net.minecraft.client.main.Main "]
+            })
+        );
+        assert_eq!(merged["id"], "fabric-loader-0.15.0-1.20.4");
+        assert!(!merged.contains_key("inheritsFrom"));
+    }
+
+    #[test]
+    fn jar_defaults_to_parent_id() {
+        let parent = get_object(json!({ "id": "1.20.4" }));
+        let child = get_object(json!({ "id": "forge-49.0.3", "inheritsFrom": "1.20.4" }));
+        assert_eq!(merge_manifests(parent, child)["jar"], "1.20.4");
+
+        let parent = get_object(json!({ "id": "1.20.4" }));
+        let child = get_object(json!({ "id": "custom", "jar": "1.20.3" }));
+        assert_eq!(merge_manifests(parent, child)["jar"], "1.20.3");
+    }
+
+    #[test]
+    fn resolves_inheritance_chain() {
+        let (_dir, launcher, _) = get_launcher();
+        launcher
+            .write_minecraft_manifest(&json!({ "id": "1.20.4", "mainClass": "vanilla" }))
+            .unwrap();
+        launcher
+            .write_minecraft_manifest(&json!({
+                "id": "loader",
+                "inheritsFrom": "1.20.4",
+                "mainClass": "loader"
+            }))
+            .unwrap();
+        launcher
+            .write_minecraft_manifest(&json!({ "id": "modpack", "inheritsFrom": "loader" }))
+            .unwrap();
+
+        let manifest = launcher
+            .resolve_manifest("modpack", &mut Vec::new())
+            .unwrap();
+
+        assert_eq!(manifest["id"], "modpack");
+        assert_eq!(manifest["mainClass"], "loader");
+        assert_eq!(manifest["jar"], "1.20.4");
+    }
+
+    #[test]
+    fn circular_inheritance_fails() {
+        let (_dir, launcher, _) = get_launcher();
+        launcher
+            .write_minecraft_manifest(&json!({ "id": "a", "inheritsFrom": "b" }))
+            .unwrap();
+        launcher
+            .write_minecraft_manifest(&json!({ "id": "b", "inheritsFrom": "a" }))
+            .unwrap();
+
+        match launcher.resolve_manifest("a", &mut Vec::new()) {
+            Err(Error::InvalidManifest(message)) => assert!(message.contains("a -> b -> a")),
+            _ => panic!("expected a circular inheritsFrom error"),
+        }
+    }
+
+    #[test]
+    fn missing_parent_fails() {
+        let (_dir, launcher, _) = get_launcher();
+        launcher
+            .write_minecraft_manifest(&json!({ "id": "loader", "inheritsFrom": "1.20.4" }))
+            .unwrap();
+
+        match launcher.resolve_manifest("loader", &mut Vec::new()) {
+            Err(Error::VersionNotFound(version)) => assert_eq!(version, "1.20.4"),
+            _ => panic!("expected the parent version to be missing"),
+        }
+    }
+
+    #[test]
+    fn patch_versions_are_stored_apart() {
+        let (_dir, launcher, _) = get_launcher();
+        launcher
+            .write_minecraft_manifest(&json!({ "id": "1.20.1", "mainClass": "old" }))
+            .unwrap();
+        launcher
+            .write_minecraft_manifest(&json!({ "id": "1.20.4", "mainClass": "new" }))
+            .unwrap();
+
+        let manifest = launcher
+            .resolve_manifest("1.20.1", &mut Vec::new())
+            .unwrap();
+        assert_eq!(manifest["mainClass"], "old");
+        let manifest = launcher
+            .resolve_manifest("1.20.4", &mut Vec::new())
+            .unwrap();
+        assert_eq!(manifest["mainClass"], "new");
+    }
+}
@@ -1,135 +1,317 @@
 use crate::{
-    download_file,
+    cancel::CancellationToken,
+    download::{self, Download, DownloadOptions},
+    endpoints,
+    error::{Error, Result},
     launchermeta::{Artifact, Library, MinecraftMeta},
-    BASE_DIR,
+    progress::Progress,
+    rules::{self, Environment},
+    runtime, Launcher,
 };
-use std::{fs, io, path::PathBuf};
+use std::{collections::HashSet, fs, io, path::PathBuf};
 
-use anyhow::Result;
+use url::Url;
 
-lazy_static! {
-    static ref LIBRARIES_DIR: PathBuf = BASE_DIR.join("libraries");
-    static ref NATIVES_TMP_DIR: PathBuf = BASE_DIR.join("natives-tmp");
-    static ref MINECRAFT_CLIENTS_DIR: PathBuf =
-        LIBRARIES_DIR.join("com").join("mojang").join("minecraft");
-    static ref OS: String = std::env::consts::OS.replace("macos", "osx");
-}
-
-fn download_client_jar(minecraft_meta: &MinecraftMeta) -> Result<()> {
-    let path = MINECRAFT_CLIENTS_DIR
-        .join(&minecraft_meta.id)
-        .join(format!("minecraft-{}-client", &minecraft_meta.id))
-        .with_extension("jar");
+/// converts group:artifact:version[:classifier][@extension] into a maven repository path
+pub(crate) fn get_maven_path(name: &str) -> Option<String> {
+    let (name, extension) = name.split_once('@').unwrap_or((name, "jar"));
 
-    download_file(&minecraft_meta.downloads.client.url, &path)?;
+    let mut parts = name.split(':');
+    let group = parts.next()?;
+    let artifact = parts.next()?;
+    let version = parts.next()?;
+    let file_name = match parts.next() {
+        Some(classifier) => format!("{artifact}-{version}-{classifier}.{extension}"),
+        None => format!("{artifact}-{version}.{extension}"),
+    };
 
-    Ok(())
+    Some(format!(
+        "{}/{artifact}/{version}/{file_name}",
+        group.replace('.', "/")
+    ))
 }
 
-fn download_artifact(artifact: &Artifact) -> Result<()> {
-    let path = LIBRARIES_DIR.join(&artifact.path);
-    download_file(&artifact.url, &path)?;
+/// points a listed download url at libraries_url when it uses the default libraries host
+fn use_libraries_url(mut artifact: Artifact, libraries_url: &Url) -> Artifact {
+    let url = artifact
+        .url
+        .as_ref()
+        .and_then(|url| download::replace_prefix(url, endpoints::LIBRARIES_URL, libraries_url));
+    if url.is_some() {
+        artifact.url = url;
+    }
 
-    Ok(())
+    artifact
 }
 
-// lazy/hacky implementation
-// it kinda works but is's not flexible
-fn is_valid_lib(lib: &&Library) -> bool {
-    if lib.rules.is_none() {
-        return true;
+/// libraries without a download url are fetched from their own maven repository, or libraries_url
+pub(crate) fn get_lib_artifact(lib: &Library, libraries_url: &Url) -> Option<Artifact> {
+    if let Some(downloads) = &lib.downloads {
+        let artifact = downloads.artifact.clone()?;
+        return Some(use_libraries_url(artifact, libraries_url));
     }
 
-    let rules = lib.rules.as_ref().unwrap();
+    let path = get_maven_path(&lib.name)?;
+    let base_url = lib.url.as_ref().unwrap_or(libraries_url);
+    let url = base_url.join(&path).ok()?;
 
-    (rules.len() == 1 && OS.eq("osx")) || (rules.len() == 2 && OS.ne("osx"))
+    Some(Artifact {
+        path,
+        url: Some(url),
+        sha1: lib.sha1.clone(),
+        size: lib.size,
+    })
+}
+
+fn is_valid_lib(lib: &Library, env: &Environment) -> bool {
+    match &lib.rules {
+        Some(rules) => rules::is_allowed(rules, env),
+        None => true,
+    }
 }
 
-fn get_valid_libs(minecraft_meta: &MinecraftMeta) -> Vec<&Library> {
+fn get_valid_libs<'a>(minecraft_meta: &'a MinecraftMeta, env: &Environment) -> Vec<&'a Library> {
     minecraft_meta
         .libraries
         .iter()
-        .filter(is_valid_lib)
+        .filter(|lib| is_valid_lib(lib, env))
         .collect()
 }
 
-fn get_native_artifact(lib: &&Library) -> Option<Artifact> {
-    if lib.natives.is_some() {
-        let natives = lib.natives.as_ref().unwrap();
-        if natives.contains_key(OS.as_str()) {
-            let artifact =
-                lib.downloads.classifiers.as_ref().unwrap()[&natives[OS.as_str()]].clone();
-            return Some(artifact);
-        }
-    }
+fn get_native_artifact(lib: &Library, env: &Environment, libraries_url: &Url) -> Option<Artifact> {
+    let classifier = lib
+        .natives
+        .as_ref()?
+        .get(&env.os_name)?
+        .replace("${arch}", env.arch_bits());
 
-    None
+    lib.downloads
+        .as_ref()?
+        .classifiers
+        .as_ref()?
+        .get(&classifier)
+        .cloned()
+        .map(|artifact| use_libraries_url(artifact, libraries_url))
 }
 
-fn get_artifacts(libs: &Vec<&Library>) -> Vec<Artifact> {
+fn get_artifacts(libs: &[&Library], libraries_url: &Url) -> Vec<Artifact> {
     libs.iter()
-        .map(|lib| lib.downloads.artifact.clone())
+        .filter_map(|lib| get_lib_artifact(lib, libraries_url))
         .collect()
 }
 
-fn get_native_artifacts(libs: &Vec<&Library>) -> Vec<Artifact> {
+fn get_native_artifacts(
+    libs: &[&Library],
+    env: &Environment,
+    libraries_url: &Url,
+) -> Vec<Artifact> {
     libs.iter()
-        .map(get_native_artifact)
-        .filter_map(|a| a)
+        .filter_map(|lib| get_native_artifact(lib, env, libraries_url))
         .collect()
 }
 
-pub fn extract_natives(native_artifacts: &Vec<Artifact>) -> Result<PathBuf> {
-    fs::remove_dir_all(NATIVES_TMP_DIR.as_path())?;
-    fs::create_dir_all(NATIVES_TMP_DIR.as_path())?;
+impl Launcher {
+    pub fn get_libraries_dir(&self) -> PathBuf {
+        self.get_root_dir().join("libraries")
+    }
+
+    fn get_natives_dir(&self) -> PathBuf {
+        self.get_root_dir().join("natives-tmp")
+    }
+
+    pub fn get_client_jar_path(&self, minecraft_meta: &MinecraftMeta) -> PathBuf {
+        let id = minecraft_meta.get_jar_id();
+
+        self.get_libraries_dir()
+            .join("com")
+            .join("mojang")
+            .join("minecraft")
+            .join(id)
+            .join(format!("minecraft-{}-client", id))
+            .with_extension("jar")
+    }
+
+    pub fn get_artifact_path(&self, artifact: &Artifact) -> PathBuf {
+        self.get_libraries_dir().join(&artifact.path)
+    }
+
+    fn get_client_jar_download(&self, minecraft_meta: &MinecraftMeta) -> Download {
+        let client = &minecraft_meta.downloads.client;
+
+        Download {
+            url: client.url.clone(),
+            path: self.get_client_jar_path(minecraft_meta),
+            sha1: client.sha1.clone(),
+            size: client.size,
+        }
+    }
+
+    pub(crate) async fn download_client_jar_async(
+        &self,
+        minecraft_meta: &MinecraftMeta,
+        options: &DownloadOptions,
+        progress: &dyn Progress,
+    ) -> Result<()> {
+        let download = self.get_client_jar_download(minecraft_meta);
+
+        self.download_file_async(
+            &download.url,
+            &download.path,
+            download.sha1.as_deref(),
+            download.size,
+            options,
+            progress,
+        )
+        .await
+    }
+
+    pub fn download_client_jar(
+        &self,
+        minecraft_meta: &MinecraftMeta,
+        options: &DownloadOptions,
+        progress: &dyn Progress,
+    ) -> Result<()> {
+        runtime::block_on(self.download_client_jar_async(minecraft_meta, options, progress))
+    }
+
+    /// returns the downloads of the artifacts, those without a url must already be present
+    fn get_artifact_downloads(&self, artifacts: &[Artifact]) -> Result<Vec<Download>> {
+        let mut downloads = Vec::new();
+        let mut seen = HashSet::new();
+
+        for artifact in artifacts {
+            let path = self.get_artifact_path(artifact);
+            if !seen.insert(path.clone()) {
+                continue;
+            }
+
+            match &artifact.url {
+                Some(url) => downloads.push(Download {
+                    url: url.clone(),
+                    path,
+                    sha1: artifact.sha1.clone(),
+                    size: artifact.size,
+                }),
+                None if path.is_file() => {}
+                None => return Err(Error::MissingArtifact(artifact.path.clone())),
+            }
+        }
+
+        Ok(downloads)
+    }
+
+    pub(crate) async fn download_artifacts_async(
+        &self,
+        artifacts: &[Artifact],
+        options: &DownloadOptions,
+        progress: &dyn Progress,
+    ) -> Result<()> {
+        let downloads = self.get_artifact_downloads(artifacts)?;
+
+        self.download_all_async(&downloads, options, progress).await
+    }
 
-    for artifact in native_artifacts {
-        let path = LIBRARIES_DIR.join(&artifact.path);
-        let jarfile = fs::File::open(path)?;
+    /// downloads the artifacts, those without a url must already be present
+    pub fn download_artifacts(
+        &self,
+        artifacts: &[Artifact],
+        options: &DownloadOptions,
+        progress: &dyn Progress,
+    ) -> Result<()> {
+        runtime::block_on(self.download_artifacts_async(artifacts, options, progress))
+    }
+
+    pub fn extract_natives(
+        &self,
+        native_artifacts: &Vec<Artifact>,
+        cancel: &CancellationToken,
+    ) -> Result<PathBuf> {
+        let natives_dir = self.get_natives_dir();
+        if natives_dir.exists() {
+            fs::remove_dir_all(&natives_dir)?;
+        }
+        fs::create_dir_all(&natives_dir)?;
 
-        let mut archive = zip::ZipArchive::new(jarfile)?;
+        for artifact in native_artifacts {
+            cancel.check()?;
 
-        for i in 0..archive.len() {
-            let mut file = archive.by_index(i)?;
+            let path = self.get_artifact_path(artifact);
+            let jarfile = fs::File::open(path)?;
 
-            let outpath = match file.enclosed_name() {
-                Some(path) => NATIVES_TMP_DIR.join(path),
-                None => continue,
-            };
+            let mut archive = zip::ZipArchive::new(jarfile)?;
 
-            if (&*file.name()).ends_with('/') {
-                fs::create_dir_all(&outpath)?;
-            } else {
-                if let Some(p) = outpath.parent() {
-                    if !p.exists() {
-                        fs::create_dir_all(&p)?;
+            for i in 0..archive.len() {
+                let mut file = archive.by_index(i)?;
+
+                let outpath = match file.enclosed_name() {
+                    Some(path) => natives_dir.join(path),
+                    None => continue,
+                };
+
+                if file.name().ends_with('/') {
+                    fs::create_dir_all(&outpath)?;
+                } else {
+                    if let Some(p) = outpath.parent() {
+                        if !p.exists() {
+                            fs::create_dir_all(p)?;
+                        }
                     }
+                    let mut outfile = fs::File::create(&outpath)?;
+                    io::copy(&mut file, &mut outfile)?;
                 }
-                let mut outfile = fs::File::create(&outpath)?;
-                io::copy(&mut file, &mut outfile)?;
             }
         }
+
+        Ok(natives_dir)
     }
 
-    Ok(NATIVES_TMP_DIR.as_path().into())
-}
+    pub(crate) async fn extract_natives_async(
+        &self,
+        native_artifacts: Vec<Artifact>,
+        cancel: &CancellationToken,
+    ) -> Result<PathBuf> {
+        let launcher = self.clone();
+        let cancel = cancel.clone();
 
-pub fn download_libraries(
-    minecraft_meta: &MinecraftMeta,
-) -> Result<(Vec<Artifact>, Vec<Artifact>)> {
-    download_client_jar(minecraft_meta)?;
+        runtime::spawn_blocking(move || launcher.extract_natives(&native_artifacts, &cancel)).await
+    }
 
-    let libs = get_valid_libs(minecraft_meta);
-    let artifacts = get_artifacts(&libs);
-    let native_artifacts = get_native_artifacts(&libs);
+    pub(crate) async fn download_libraries_async(
+        &self,
+        minecraft_meta: &MinecraftMeta,
+        env: &Environment,
+        progress: &dyn Progress,
+        cancel: &CancellationToken,
+    ) -> Result<(Vec<Artifact>, Vec<Artifact>)> {
+        let options = self.get_download_options(cancel);
 
-    for artifact in &artifacts {
-        download_artifact(artifact)?;
-    }
+        let libs = get_valid_libs(minecraft_meta, env);
+        let libraries_url = self.get_config().endpoints.libraries;
+        let artifacts = get_artifacts(&libs, &libraries_url);
+        let native_artifacts = get_native_artifacts(&libs, env, &libraries_url);
 
-    for artifact in &native_artifacts {
-        download_artifact(artifact)?;
+        let all_artifacts: Vec<Artifact> = artifacts
+            .iter()
+            .chain(native_artifacts.iter())
+            .cloned()
+            .collect();
+
+        // the client jar is part of the same batch, so the reported total covers it
+        let mut downloads = vec![self.get_client_jar_download(minecraft_meta)];
+        downloads.extend(self.get_artifact_downloads(&all_artifacts)?);
+        self.download_all_async(&downloads, &options, progress)
+            .await?;
+
+        Ok((artifacts, native_artifacts))
     }
 
-    Ok((artifacts, native_artifacts))
+    pub fn download_libraries(
+        &self,
+        minecraft_meta: &MinecraftMeta,
+        env: &Environment,
+        progress: &dyn Progress,
+        cancel: &CancellationToken,
+    ) -> Result<(Vec<Artifact>, Vec<Artifact>)> {
+        runtime::block_on(self.download_libraries_async(minecraft_meta, env, progress, cancel))
+    }
 }
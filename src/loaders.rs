@@ -0,0 +1,125 @@
+use serde::{Deserialize, Serialize};
+
+use tracing::{info, instrument};
+
+use crate::{cancel::CancellationToken, error::Result, runtime, Launcher};
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LoaderKind {
+    Fabric,
+    Quilt,
+    Forge,
+    NeoForge,
+}
+
+impl LoaderKind {
+    /// name stored as the instance version_type
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            Self::Fabric => "Fabric",
+            Self::Quilt => "Quilt",
+            Self::Forge => "Forge",
+            Self::NeoForge => "NeoForge",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LoaderVersion {
+    pub version: String,
+    pub stable: bool,
+}
+
+/// Mod loader installed in an instance
+#[derive(Serialize, Deserialize)]
+pub struct Loader {
+    pub kind: LoaderKind,
+    pub version: String,
+    /// id of the loader version JSON, inheriting from the vanilla one
+    pub version_id: String,
+}
+
+impl Launcher {
+    pub(crate) async fn get_loader_versions_async(
+        &self,
+        kind: LoaderKind,
+        minecraft_version: &str,
+    ) -> Result<Vec<LoaderVersion>> {
+        match kind {
+            LoaderKind::Fabric => {
+                self.get_fabric_loader_versions_async(minecraft_version)
+                    .await
+            }
+            LoaderKind::Quilt => {
+                self.get_quilt_loader_versions_async(minecraft_version)
+                    .await
+            }
+            LoaderKind::Forge | LoaderKind::NeoForge => {
+                self.get_forge_loader_versions_async(kind, minecraft_version)
+                    .await
+            }
+        }
+    }
+
+    pub fn get_loader_versions(
+        &self,
+        kind: LoaderKind,
+        minecraft_version: &str,
+    ) -> Result<Vec<LoaderVersion>> {
+        runtime::block_on(self.get_loader_versions_async(kind, minecraft_version))
+    }
+
+    #[instrument(skip_all, fields(
+        kind = kind.get_name(),
+        minecraft_version = %minecraft_version,
+        loader_version = %loader_version
+    ))]
+    pub(crate) async fn install_loader_async(
+        &self,
+        kind: LoaderKind,
+        minecraft_version: &str,
+        loader_version: &str,
+        cancel: &CancellationToken,
+    ) -> Result<Loader> {
+        cancel.check()?;
+        info!("installing loader");
+
+        let version_id = match kind {
+            LoaderKind::Fabric => {
+                self.download_fabric_profile_async(minecraft_version, loader_version)
+                    .await?
+            }
+            LoaderKind::Quilt => {
+                self.download_quilt_profile_async(minecraft_version, loader_version)
+                    .await?
+            }
+            LoaderKind::Forge | LoaderKind::NeoForge => {
+                self.install_forge_async(kind, minecraft_version, loader_version, cancel)
+                    .await?
+            }
+        };
+
+        Ok(Loader {
+            kind,
+            version: loader_version.to_string(),
+            version_id,
+        })
+    }
+
+    /// downloads the loader profile next to the vanilla manifest,
+    /// running the installer for forge and neoforge
+    pub fn install_loader(
+        &self,
+        kind: LoaderKind,
+        minecraft_version: &str,
+        loader_version: &str,
+        cancel: &CancellationToken,
+    ) -> Result<Loader> {
+        runtime::block_on(self.install_loader_async(
+            kind,
+            minecraft_version,
+            loader_version,
+            cancel,
+        ))
+    }
+}
@@ -1,13 +1,21 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, time};
 
-use anyhow::Result;
 use chrono::{prelude::*, Duration};
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use tracing::{debug, instrument};
 use url::Url;
 
+use crate::{
+    accounts::DeviceCode,
+    cancel::CancellationToken,
+    error::{AuthError, Error, Result},
+    http::HttpRequest,
+    Launcher,
+};
+
 const CLIENT_ID: &str = "2000ea79-d993-4591-b9c4-e678f82ae1db";
 const SCOPE: &str = "XboxLive.signin offline_access";
 const REDIRECT_URI: &str = "http://127.0.0.1:3003";
@@ -24,9 +32,7 @@ lazy_static! {
     };
     static ref CODE_CHALLENGE: String = {
         let hash = Sha256::digest(CODE_VERIFIER.as_str());
-        let encoded = base64_url::encode(&hash);
-
-        encoded
+        base64_url::encode(&hash)
     };
     static ref STATE: String = {
         let rand_string: String = thread_rng()
@@ -39,42 +45,58 @@ lazy_static! {
     };
 }
 
-pub fn get_auth_url() -> Result<Url, url::ParseError> {
-    Url::parse_with_params(
-        "https://login.microsoftonline.com/consumers/oauth2/v2.0/authorize",
-        &[
-            ("client_id", CLIENT_ID),
-            ("response_type", "code"),
-            ("redirect_uri", REDIRECT_URI),
-            ("response_mode", "query"),
-            ("scope", SCOPE),
-            ("state", STATE.as_ref()),
-            ("code_challenge", CODE_CHALLENGE.as_ref()),
-            ("code_challenge_method", "S256"),
-        ],
-    )
-}
-
-fn listen_login_callback() -> Result<String> {
-    let server = tiny_http::Server::http("127.0.0.1:3003").unwrap();
-    let request = server.recv()?;
-
-    let url = Url::parse(&format!("{}{}", REDIRECT_URI, request.url()))?;
+/// returns the code of the redirect to path, checking its state
+fn get_callback_code(path: &str) -> Result<String> {
+    let url = Url::parse(&format!("{}{}", REDIRECT_URI, path))?;
     let hash_query: HashMap<_, _> = url.query_pairs().into_owned().collect();
 
-    let state = hash_query
-        .get("state")
-        .ok_or(anyhow!("Auth2 state not found"))?;
+    let state = hash_query.get("state").ok_or(AuthError::InvalidState)?;
 
     if state.ne(STATE.as_str()) {
-        bail!("Invalid auth2 state");
+        return Err(AuthError::InvalidState.into());
     }
 
-    let code = hash_query.get("code").ok_or(anyhow!("Code not found"))?;
+    let code = hash_query.get("code").ok_or(AuthError::MissingCode)?;
+
+    Ok(code.to_string())
+}
+
+#[cfg(not(feature = "tokio"))]
+async fn listen_login_callback() -> Result<String> {
+    let server = tiny_http::Server::http("127.0.0.1:3003").map_err(std::io::Error::other)?;
+    let request = server.recv()?;
+
+    let code = get_callback_code(request.url())?;
 
     request.respond(tiny_http::Response::from_string("You can close this tab"))?;
 
-    Ok(code.to_string())
+    Ok(code)
+}
+
+#[cfg(feature = "tokio")]
+async fn listen_login_callback() -> Result<String> {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:3003").await?;
+    let (stream, _) = listener.accept().await?;
+    let mut stream = BufReader::new(stream);
+
+    // only the request line is needed: GET /?code=...&state=... HTTP/1.1
+    let mut request_line = String::new();
+    stream.read_line(&mut request_line).await?;
+    let path = request_line.split(' ').nth(1).unwrap_or_default();
+
+    let code = get_callback_code(path)?;
+
+    let body = "You can close this tab";
+    let response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    );
+    stream.get_mut().write_all(response.as_bytes()).await?;
+
+    Ok(code)
 }
 
 #[derive(Serialize, Deserialize)]
@@ -85,37 +107,278 @@ pub struct MsAccount {
     refresh_token: String,
 }
 
-pub fn get_account() -> Result<MsAccount> {
-    let code = listen_login_callback()?;
+/// body of a refused token request
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: String,
+    #[serde(default)]
+    error_description: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    token_type: String,
+    expires_in: i64,
+    refresh_token: String,
+}
 
-    #[derive(Deserialize)]
-    struct Response {
-        access_token: String,
-        token_type: String,
-        expires_in: i64,
-        scope: String,
-        refresh_token: String,
+impl From<TokenResponse> for MsAccount {
+    fn from(resp: TokenResponse) -> Self {
+        Self {
+            access_token: resp.access_token,
+            token_type: resp.token_type,
+            expires: Local::now() + Duration::seconds(resp.expires_in),
+            refresh_token: resp.refresh_token,
+        }
     }
+}
 
-    const URL: &str = "https://login.microsoftonline.com/consumers/oauth2/v2.0/token";
+impl Launcher {
+    pub(crate) fn get_ms_auth_url(&self) -> Result<Url> {
+        let mut url = self
+            .get_config()
+            .endpoints
+            .microsoft_oauth
+            .join("authorize")?;
+        url.query_pairs_mut().extend_pairs(&[
+            ("client_id", CLIENT_ID),
+            ("response_type", "code"),
+            ("redirect_uri", REDIRECT_URI),
+            ("response_mode", "query"),
+            ("scope", SCOPE),
+            ("state", STATE.as_ref()),
+            ("code_challenge", CODE_CHALLENGE.as_ref()),
+            ("code_challenge_method", "S256"),
+        ]);
 
-    let form = [
-        ("client_id", CLIENT_ID),
-        ("scope", SCOPE),
-        ("code", &code),
-        ("redirect_uri", REDIRECT_URI),
-        ("grant_type", "authorization_code"),
-        ("code_verifier", CODE_VERIFIER.as_ref()),
-    ];
+        Ok(url)
+    }
 
-    let resp: Response = ureq::post(URL).send_form(&form)?.into_json()?;
+    #[instrument(skip_all)]
+    pub(crate) async fn get_ms_account(&self) -> Result<MsAccount> {
+        debug!("waiting for the login callback on {}", REDIRECT_URI);
+        let code = listen_login_callback().await?;
 
-    let token = MsAccount {
-        access_token: resp.access_token,
-        token_type: resp.token_type,
-        expires: Local::now() + Duration::seconds(resp.expires_in),
-        refresh_token: resp.refresh_token,
-    };
+        let form = [
+            ("client_id", CLIENT_ID),
+            ("scope", SCOPE),
+            ("code", &code),
+            ("redirect_uri", REDIRECT_URI),
+            ("grant_type", "authorization_code"),
+            ("code_verifier", CODE_VERIFIER.as_ref()),
+        ];
 
-    Ok(token)
+        self.request_ms_token(&form).await
+    }
+
+    /// renews the access token, fails with AuthError::SignInExpired
+    /// when the refresh token was revoked, is too old or needs the user to sign in again
+    #[instrument(skip_all)]
+    pub(crate) async fn refresh_ms_account(&self, msa: &MsAccount) -> Result<MsAccount> {
+        let form = [
+            ("client_id", CLIENT_ID),
+            ("scope", SCOPE),
+            ("refresh_token", &msa.refresh_token),
+            ("grant_type", "refresh_token"),
+        ];
+
+        match self.request_ms_token(&form).await {
+            Err(Error::Auth(AuthError::OAuth(error)))
+                if error == "invalid_grant" || error == "interaction_required" =>
+            {
+                Err(AuthError::SignInExpired.into())
+            }
+            result => result,
+        }
+    }
+
+    /// fails with AuthError::OAuth when the request is refused
+    async fn request_ms_token(&self, form: &[(&str, &str)]) -> Result<MsAccount> {
+        let url = self.get_config().endpoints.microsoft_oauth.join("token")?;
+        let request = HttpRequest::post(&url).form(form);
+        let resp = self.send_request_raw(request).await?;
+
+        if resp.status == 400 {
+            let error: ErrorResponse = resp.into_json().await?;
+            debug!(
+                "token request refused with {}: {}",
+                error.error, error.error_description
+            );
+            return Err(AuthError::OAuth(error.error).into());
+        }
+        if resp.status >= 400 {
+            return Err(Error::Status {
+                url,
+                status: resp.status,
+            });
+        }
+        let resp: TokenResponse = resp.into_json().await?;
+
+        Ok(resp.into())
+    }
+
+    /// starts a device authorization grant, the user signs in on another device
+    pub(crate) async fn request_device_code(&self) -> Result<DeviceCode> {
+        #[derive(Deserialize)]
+        struct Response {
+            device_code: String,
+            user_code: String,
+            verification_uri: Url,
+            expires_in: i64,
+            interval: u64,
+            message: String,
+        }
+
+        let url = self
+            .get_config()
+            .endpoints
+            .microsoft_oauth
+            .join("devicecode")?;
+        let request = HttpRequest::post(&url).form(&[("client_id", CLIENT_ID), ("scope", SCOPE)]);
+        let resp: Response = self.send_request(request).await?.into_json().await?;
+
+        let device_code = DeviceCode {
+            user_code: resp.user_code,
+            verification_uri: resp.verification_uri,
+            message: resp.message,
+            expires: Local::now() + Duration::seconds(resp.expires_in),
+            device_code: resp.device_code,
+            interval: resp.interval,
+        };
+
+        Ok(device_code)
+    }
+
+    /// polls until the user completes the sign-in started by request_device_code
+    #[instrument(skip_all)]
+    pub(crate) async fn get_ms_account_with_device_code(
+        &self,
+        device_code: &DeviceCode,
+        cancel: &CancellationToken,
+    ) -> Result<MsAccount> {
+        let form = [
+            ("client_id", CLIENT_ID),
+            ("grant_type", "urn:ietf:params:oauth:grant-type:device_code"),
+            ("device_code", &device_code.device_code),
+        ];
+
+        let mut interval = device_code.interval;
+        debug!("waiting for the device code sign-in");
+        loop {
+            if Local::now() >= device_code.expires {
+                return Err(AuthError::DeviceCodeExpired.into());
+            }
+            cancel
+                .sleep_async(time::Duration::from_secs(interval))
+                .await?;
+
+            let error = match self.request_ms_token(&form).await {
+                Err(Error::Auth(AuthError::OAuth(error))) => error,
+                result => return result,
+            };
+            match error.as_str() {
+                "authorization_pending" => continue,
+                // the server asks for 5 more seconds between polls
+                "slow_down" => interval += 5,
+                "authorization_declined" => return Err(AuthError::SignInDeclined.into()),
+                "expired_token" => return Err(AuthError::DeviceCodeExpired.into()),
+                _ => return Err(AuthError::OAuth(error).into()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::{http::MemoryTransport, launcher::tests::get_launcher, runtime};
+
+    pub(crate) fn respond_token(transport: &MemoryTransport, status: u16, body: Value) {
+        let url =
+            Url::parse("https://login.microsoftonline.com/consumers/oauth2/v2.0/token").unwrap();
+        transport.respond("POST", &url, status, body.to_string());
+    }
+
+    pub(crate) fn get_token_response(access_token: &str) -> Value {
+        json!({
+            "access_token": access_token,
+            "token_type": "Bearer",
+            "expires_in": 3600,
+            "refresh_token": "new-refresh-token"
+        })
+    }
+
+    fn get_device_code() -> DeviceCode {
+        DeviceCode {
+            user_code: "ABCD-EFGH".to_string(),
+            verification_uri: Url::parse("https://microsoft.com/link").unwrap(),
+            message: String::new(),
+            expires: Local::now() + Duration::minutes(15),
+            device_code: "device-code".to_string(),
+            interval: 0,
+        }
+    }
+
+    fn get_stored_account() -> MsAccount {
+        MsAccount {
+            access_token: "ms-token".to_string(),
+            token_type: "Bearer".to_string(),
+            expires: Local::now(),
+            refresh_token: "refresh-token".to_string(),
+        }
+    }
+
+    #[test]
+    fn device_code_polling() {
+        let (_dir, launcher, transport) = get_launcher();
+        respond_token(&transport, 400, json!({ "error": "authorization_pending" }));
+        respond_token(&transport, 400, json!({ "error": "slow_down" }));
+        respond_token(&transport, 200, get_token_response("ms-token"));
+
+        let msa = runtime::block_on(
+            launcher.get_ms_account_with_device_code(&get_device_code(), &CancellationToken::new()),
+        )
+        .unwrap();
+
+        assert_eq!(msa.access_token, "ms-token");
+        assert_eq!(transport.get_requests().len(), 3);
+    }
+
+    #[test]
+    fn device_code_unknown_error_is_kept() {
+        let (_dir, launcher, transport) = get_launcher();
+        respond_token(&transport, 400, json!({ "error": "invalid_client" }));
+
+        let result = runtime::block_on(
+            launcher.get_ms_account_with_device_code(&get_device_code(), &CancellationToken::new()),
+        );
+
+        match result {
+            Err(Error::Auth(AuthError::OAuth(error))) => assert_eq!(error, "invalid_client"),
+            _ => panic!("expected the OAuth error"),
+        }
+    }
+
+    #[test]
+    fn revoked_refresh_token_expires_the_sign_in() {
+        let (_dir, launcher, transport) = get_launcher();
+        respond_token(&transport, 400, json!({ "error": "invalid_grant" }));
+
+        let result = runtime::block_on(launcher.refresh_ms_account(&get_stored_account()));
+
+        assert!(matches!(result, Err(Error::Auth(AuthError::SignInExpired))));
+    }
+
+    #[test]
+    fn other_refresh_errors_are_kept() {
+        let (_dir, launcher, transport) = get_launcher();
+        respond_token(&transport, 400, json!({ "error": "invalid_scope" }));
+
+        let result = runtime::block_on(launcher.refresh_ms_account(&get_stored_account()));
+
+        assert!(matches!(result, Err(Error::Auth(AuthError::OAuth(_)))));
+    }
 }
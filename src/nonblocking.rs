@@ -0,0 +1,237 @@
+//! Async versions of the long running Launcher operations, enabled by the `tokio` feature.
+//!
+//! Requests go through the non-blocking transport and downloads run as tokio tasks, limited
+//! to download_workers at a time. Dropping a future stops the operation: in-flight requests
+//! are abandoned and the download tasks are aborted. A CancellationToken stops an operation
+//! from elsewhere, e.g. another task.
+
+use std::ops::Deref;
+
+use tokio::process::Child;
+use url::Url;
+
+use crate::{
+    accounts::{DeviceCode, Session},
+    cancel::CancellationToken,
+    download::{Download, DownloadOptions},
+    error::Result,
+    launchermeta::Version,
+    loaders::{Loader, LoaderKind, LoaderVersion},
+    profile::{McAccount, UserProfile},
+    progress::Progress,
+    Launcher,
+};
+
+/// Handle to a Launcher with async methods, clones share the config and transport.
+///
+/// It derefs to the Launcher for the quick, filesystem only methods; the blocking network
+/// methods must not be called from async code, use the ones defined here instead.
+#[derive(Clone)]
+pub struct AsyncLauncher(Launcher);
+
+impl From<Launcher> for AsyncLauncher {
+    fn from(launcher: Launcher) -> Self {
+        Self(launcher)
+    }
+}
+
+impl Deref for AsyncLauncher {
+    type Target = Launcher;
+
+    fn deref(&self) -> &Launcher {
+        &self.0
+    }
+}
+
+impl AsyncLauncher {
+    pub fn new(launcher: Launcher) -> Self {
+        launcher.into()
+    }
+
+    pub async fn get_minecraft_versions(&self) -> Result<Vec<Version>> {
+        self.0.get_minecraft_versions_async().await
+    }
+
+    pub async fn download_minecraft_manifest(
+        &self,
+        version: &Version,
+        cancel: &CancellationToken,
+    ) -> Result<()> {
+        self.0
+            .download_minecraft_manifest_async(version, cancel)
+            .await
+    }
+
+    pub async fn get_loader_versions(
+        &self,
+        kind: LoaderKind,
+        minecraft_version: &str,
+    ) -> Result<Vec<LoaderVersion>> {
+        self.0
+            .get_loader_versions_async(kind, minecraft_version)
+            .await
+    }
+
+    pub async fn install_loader(
+        &self,
+        kind: LoaderKind,
+        minecraft_version: &str,
+        loader_version: &str,
+        cancel: &CancellationToken,
+    ) -> Result<Loader> {
+        self.0
+            .install_loader_async(kind, minecraft_version, loader_version, cancel)
+            .await
+    }
+
+    /// version is an entry returned by get_minecraft_versions,
+    /// loader is the mod loader to install with its version, None for vanilla
+    pub async fn new_instance(
+        &self,
+        name: &str,
+        version: &Version,
+        loader: Option<(LoaderKind, &str)>,
+        cancel: &CancellationToken,
+    ) -> Result<()> {
+        self.0
+            .new_instance_async(name, version, loader, cancel)
+            .await
+    }
+
+    /// see Launcher::download_file
+    pub async fn download_file(
+        &self,
+        url: &Url,
+        path: &std::path::Path,
+        sha1: Option<&str>,
+        size: Option<u64>,
+        options: &DownloadOptions,
+        progress: &dyn Progress,
+    ) -> Result<()> {
+        self.0
+            .download_file_async(url, path, sha1, size, options, progress)
+            .await
+    }
+
+    /// see Launcher::download_all
+    pub async fn download_all(
+        &self,
+        downloads: &[Download],
+        options: &DownloadOptions,
+        progress: &dyn Progress,
+    ) -> Result<()> {
+        self.0
+            .download_all_async(downloads, options, progress)
+            .await
+    }
+
+    /// signs in through the browser and stores the account
+    pub async fn add_account(&self) -> Result<()> {
+        self.0.add_account_async().await
+    }
+
+    pub async fn get_device_code(&self) -> Result<DeviceCode> {
+        self.0.request_device_code().await
+    }
+
+    /// see Launcher::add_account_with_device_code
+    pub async fn add_account_with_device_code(
+        &self,
+        device_code: &DeviceCode,
+        cancel: &CancellationToken,
+    ) -> Result<()> {
+        self.0
+            .add_account_with_device_code_async(device_code, cancel)
+            .await
+    }
+
+    pub async fn refresh_account(&self, name: &str) -> Result<()> {
+        self.0.refresh_account_async(name).await
+    }
+
+    /// see Launcher::get_session
+    pub async fn get_session(&self, name: &str) -> Result<Session> {
+        self.0.get_session_async(name).await
+    }
+
+    pub async fn get_minecraft_account(&self, ms_access_token: &str) -> Result<McAccount> {
+        self.0.get_minecraft_account_async(ms_access_token).await
+    }
+
+    pub async fn get_user_profile(&self, mca: &McAccount) -> Result<UserProfile> {
+        self.0.get_user_profile_async(mca).await
+    }
+
+    /// prepares the instance, then starts the game as a tokio process
+    pub async fn launch(
+        &self,
+        instance_name: &str,
+        session: &Session,
+        progress: &dyn Progress,
+        cancel: &CancellationToken,
+    ) -> Result<Child> {
+        let command = self
+            .0
+            .get_launch_command(instance_name, session, progress, cancel)
+            .await?;
+        let child = tokio::process::Command::from(command).spawn()?;
+
+        Ok(child)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Local};
+
+    use super::*;
+    use crate::{accounts, launcher::tests::get_launcher, msa, profile, progress::NoProgress};
+
+    #[tokio::test]
+    async fn downloads_run_as_tasks() {
+        let (dir, launcher, transport) = get_launcher();
+        let downloads: Vec<Download> = (0..4)
+            .map(|i| Download {
+                url: Url::parse(&format!("https://example.com/{i}")).unwrap(),
+                path: dir.path().join(i.to_string()),
+                sha1: None,
+                size: Some(7),
+            })
+            .collect();
+        for download in &downloads {
+            transport.respond("GET", &download.url, 200, "content");
+        }
+
+        let options = DownloadOptions {
+            workers: 2,
+            ..DownloadOptions::default()
+        };
+        AsyncLauncher::new(launcher)
+            .download_all(&downloads, &options, &NoProgress)
+            .await
+            .unwrap();
+
+        for download in &downloads {
+            assert_eq!(std::fs::read_to_string(&download.path).unwrap(), "content");
+        }
+    }
+
+    #[tokio::test]
+    async fn session_refreshes_expired_token() {
+        let (_dir, launcher, transport) = get_launcher();
+        accounts::tests::write_account(&launcher, Local::now() - Duration::hours(1));
+        msa::tests::respond_token(
+            &transport,
+            200,
+            msa::tests::get_token_response("new-ms-token"),
+        );
+        profile::tests::respond_sign_in(&transport, "new-mc-token");
+
+        let session = AsyncLauncher::new(launcher)
+            .get_session("Steve")
+            .await
+            .unwrap();
+
+        assert_eq!(session.access_token, "new-mc-token");
+    }
+}
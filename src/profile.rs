@@ -1,135 +1,357 @@
-use anyhow::Result;
 use chrono::{DateTime, Duration, Local};
-use isahc::{RequestExt, Request, ReadResponseExt};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use tracing::{debug, info, instrument};
 
-/// returns xbl_token
-fn authenticate_with_xbl(ms_access_token: &str) -> Result<String> {
-    const AUTH_URL: &str = "https://user.auth.xboxlive.com/user/authenticate";
-
-    #[derive(Deserialize)]
-    #[serde(rename_all = "PascalCase")]
-    struct Response {
-        token: String,
-    }
-
-    let query = json!({
-        "Properties": {
-            "AuthMethod": "RPS",
-            "SiteName": "user.auth.xboxlive.com",
-            "RpsTicket": format!("d={ms_access_token}")
-        },
-        "RelyingParty": "http://auth.xboxlive.com",
-        "TokenType": "JWT"
-    });
-
-    // fallback for https://github.com/algesten/ureq/issues/470
-    let mut resp = Request::post(AUTH_URL)
-        .header("Content-Type", "application/json")
-        .body(serde_json::to_string(&query)?)?
-        .send()?;
-    let text = resp.text()?;
-    let token = serde_json::from_str::<Response>(&text)?.token;
-
-    Ok(token)
+use crate::{
+    endpoints::Endpoints,
+    error::{AuthError, Error, Result},
+    http::HttpRequest,
+    runtime, Launcher,
+};
+
+#[derive(Serialize, Deserialize)]
+pub struct McAccount {
+    pub access_token: String,
+    token_type: String,
+    expires: DateTime<Local>,
 }
 
-/// returns xsts_token and user_hash
-fn authenticate_with_xsts(xbl_token: &str) -> Result<(String, String)> {
-    const AUTH_URL: &str = "https://xsts.auth.xboxlive.com/xsts/authorize";
+impl McAccount {
+    /// true when the access token expires in less than a minute
+    pub(crate) fn is_expired(&self) -> bool {
+        self.expires - Duration::minutes(1) < Local::now()
+    }
+}
 
-    #[derive(Deserialize)]
-    struct Xui {
-        uhs: String,
+/// maps the XErr code of a refused XSTS authorization
+fn get_xsts_error(xerr: u64) -> AuthError {
+    match xerr {
+        2148916233 => AuthError::NoXboxAccount,
+        2148916235 => AuthError::XboxUnavailable,
+        2148916236 | 2148916237 => AuthError::AdultVerificationRequired,
+        2148916238 => AuthError::ChildAccount,
+        _ => AuthError::Xbox(xerr),
     }
+}
+
+#[derive(Clone, Deserialize)]
+pub struct UserProfile {
+    pub id: String,
+    pub name: String,
+}
+
+impl Launcher {
+    /// returns xbl_token
+    #[instrument(skip_all)]
+    async fn authenticate_with_xbl(
+        &self,
+        endpoints: &Endpoints,
+        ms_access_token: &str,
+    ) -> Result<String> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "PascalCase")]
+        struct Response {
+            token: String,
+        }
+
+        let query = json!({
+            "Properties": {
+                "AuthMethod": "RPS",
+                "SiteName": "user.auth.xboxlive.com",
+                "RpsTicket": format!("d={ms_access_token}")
+            },
+            "RelyingParty": "http://auth.xboxlive.com",
+            "TokenType": "JWT"
+        });
 
-    #[derive(Deserialize)]
-    struct DisplayClaims {
-        xui: Vec<Xui>,
+        let url = endpoints.xbl_auth.join("user/authenticate")?;
+        let request = HttpRequest::post(&url).json(&query)?;
+        let resp: Response = self.send_request(request).await?.into_json().await?;
+
+        Ok(resp.token)
     }
 
-    #[derive(Deserialize)]
-    #[serde(rename_all = "PascalCase")]
-    struct Response {
-        token: String,
-        display_claims: DisplayClaims,
+    /// returns xsts_token and user_hash
+    #[instrument(skip_all)]
+    async fn authenticate_with_xsts(
+        &self,
+        endpoints: &Endpoints,
+        xbl_token: &str,
+    ) -> Result<(String, String)> {
+        #[derive(Deserialize)]
+        struct Xui {
+            uhs: String,
+        }
+
+        #[derive(Deserialize)]
+        struct DisplayClaims {
+            xui: Vec<Xui>,
+        }
+
+        #[derive(Deserialize)]
+        #[serde(rename_all = "PascalCase")]
+        struct Response {
+            token: String,
+            display_claims: DisplayClaims,
+        }
+
+        let query = json!({
+            "Properties": {
+                "SandboxId": "RETAIL",
+                "UserTokens": [
+                    xbl_token
+                ]
+            },
+            "RelyingParty": "rp://api.minecraftservices.com/",
+            "TokenType": "JWT"
+        });
+
+        #[derive(Deserialize)]
+        #[serde(rename_all = "PascalCase")]
+        struct ErrorResponse {
+            x_err: u64,
+        }
+
+        let url = endpoints.xsts_auth.join("xsts/authorize")?;
+        let request = HttpRequest::post(&url).json(&query)?;
+        let resp = self.send_request_raw(request).await?;
+
+        if resp.status == 401 {
+            let error: ErrorResponse = resp.into_json().await?;
+            debug!("xsts authorization refused with XErr {}", error.x_err);
+            return Err(get_xsts_error(error.x_err).into());
+        }
+        if resp.status >= 400 {
+            return Err(Error::Status {
+                url,
+                status: resp.status,
+            });
+        }
+        let resp: Response = resp.into_json().await?;
+
+        let user_hash = resp
+            .display_claims
+            .xui
+            .into_iter()
+            .next()
+            .map(|xui| xui.uhs)
+            .ok_or(AuthError::MissingUserHash)?;
+
+        Ok((resp.token, user_hash))
     }
 
-    let query = json!({
-        "Properties": {
-            "SandboxId": "RETAIL",
-            "UserTokens": [
-                xbl_token
-            ]
-        },
-        "RelyingParty": "rp://api.minecraftservices.com/",
-        "TokenType": "JWT"
-    });
+    /// returns mc_access_token
+    #[instrument(skip_all)]
+    async fn authenticate_with_minecraft(
+        &self,
+        endpoints: &Endpoints,
+        xsts_token: &str,
+        user_hash: &str,
+    ) -> Result<McAccount> {
+        #[derive(Deserialize)]
+        struct Response {
+            access_token: String,
+            token_type: String,
+            expires_in: i64,
+        }
 
-    let resp: Response = ureq::post(AUTH_URL).send_json(query)?.into_json()?;
+        let query = json!({ "identityToken": format!("XBL3.0 x={user_hash};{xsts_token}") });
 
-    let user_hash = resp.display_claims.xui[0].uhs.clone();
+        let url = endpoints
+            .minecraft_services
+            .join("authentication/login_with_xbox")?;
+        let request = HttpRequest::post(&url).json(&query)?;
+        let resp: Response = self.send_request(request).await?.into_json().await?;
 
-    Ok((resp.token, user_hash))
-}
+        let minecraft_account = McAccount {
+            access_token: resp.access_token,
+            token_type: resp.token_type,
+            expires: Local::now() + Duration::seconds(resp.expires_in),
+        };
 
-#[derive(Serialize, Deserialize)]
-pub struct McAccount {
-    access_token: String,
-    token_type: String,
-    expires: DateTime<Local>,
-}
+        Ok(minecraft_account)
+    }
+
+    // tokens are never recorded, every span skips the arguments
+    #[instrument(skip_all)]
+    pub(crate) async fn get_minecraft_account_async(
+        &self,
+        ms_access_token: &str,
+    ) -> Result<McAccount> {
+        let endpoints = self.get_config().endpoints;
+        let xbl_token = self
+            .authenticate_with_xbl(&endpoints, ms_access_token)
+            .await?;
+        let (xsts_token, user_hash) = self.authenticate_with_xsts(&endpoints, &xbl_token).await?;
+        let minecraft_account = self
+            .authenticate_with_minecraft(&endpoints, &xsts_token, &user_hash)
+            .await?;
+        info!("signed in to minecraft services");
 
-/// returns mc_access_token
-fn authenticate_with_minecraft(xsts_token: &str, user_hash: &str) -> Result<McAccount> {
-    const AUTH_URL: &str = "https://api.minecraftservices.com/authentication/login_with_xbox";
+        Ok(minecraft_account)
+    }
 
-    #[derive(Deserialize)]
-    struct Response {
-        access_token: String,
-        token_type: String,
-        expires_in: i64,
+    pub fn get_minecraft_account(&self, ms_access_token: &str) -> Result<McAccount> {
+        runtime::block_on(self.get_minecraft_account_async(ms_access_token))
     }
 
-    let query = json!({ "identityToken": format!("XBL3.0 x={user_hash};{xsts_token}") });
+    #[instrument(skip_all)]
+    pub(crate) async fn get_user_profile_async(&self, mca: &McAccount) -> Result<UserProfile> {
+        let tt = &mca.token_type;
+        let at = &mca.access_token;
 
-    let resp: Response = ureq::post(AUTH_URL).send_json(query)?.into_json()?;
+        let url = self
+            .get_config()
+            .endpoints
+            .minecraft_services
+            .join("minecraft/profile")?;
+        let request = HttpRequest::get(&url).header("Authorization", &format!("{tt} {at}"));
+        // accounts that don't own the game have no profile
+        let resp: UserProfile = match self.send_request(request).await {
+            Err(Error::Status { status: 404, .. }) => {
+                return Err(AuthError::NoMinecraftProfile.into())
+            }
+            resp => resp?.into_json().await?,
+        };
 
-    let minecraft_account = McAccount {
-        access_token: resp.access_token,
-        token_type: resp.token_type,
-        expires: Local::now() + Duration::seconds(resp.expires_in),
-    };
+        Ok(resp)
+    }
 
-    Ok(minecraft_account)
+    /// returns user profile and access token
+    pub fn get_user_profile(&self, mca: &McAccount) -> Result<UserProfile> {
+        runtime::block_on(self.get_user_profile_async(mca))
+    }
 }
 
-pub fn get_minecraft_account(ms_access_token: &str) -> Result<McAccount> {
-    let xbl_token = authenticate_with_xbl(ms_access_token)?;
-    let (xsts_token, user_hash) = authenticate_with_xsts(&xbl_token)?;
-    let minecraft_account = authenticate_with_minecraft(&xsts_token, &user_hash)?;
+#[cfg(test)]
+pub(crate) mod tests {
+    use serde_json::{json, Value};
 
-    Ok(minecraft_account)
-}
+    use super::*;
+    use crate::{http::MemoryTransport, launcher::tests::get_launcher};
 
-#[derive(Deserialize)]
-pub struct UserProfile {
-    pub id: String,
-    pub name: String,
-}
+    fn respond_json(
+        transport: &MemoryTransport,
+        method: &str,
+        url: &str,
+        status: u16,
+        body: Value,
+    ) {
+        let url = url::Url::parse(url).unwrap();
+        transport.respond(method, &url, status, body.to_string());
+    }
 
-/// returns user profile and access token
-pub fn get_user_profile(mca: &McAccount) -> Result<UserProfile> {
-    const PROFILE_URL: &str = "https://api.minecraftservices.com/minecraft/profile";
+    fn respond_xbl(transport: &MemoryTransport) {
+        respond_json(
+            transport,
+            "POST",
+            "https://user.auth.xboxlive.com/user/authenticate",
+            200,
+            json!({ "Token": "xbl-token" }),
+        );
+    }
 
-    let tt = &mca.token_type;
-    let at = &mca.access_token;
+    /// answers every step of the sign-in after the Microsoft token, returning mc_access_token
+    pub(crate) fn respond_sign_in(transport: &MemoryTransport, mc_access_token: &str) {
+        respond_xbl(transport);
+        respond_json(
+            transport,
+            "POST",
+            "https://xsts.auth.xboxlive.com/xsts/authorize",
+            200,
+            json!({ "Token": "xsts-token", "DisplayClaims": { "xui": [{ "uhs": "user-hash" }] } }),
+        );
+        respond_json(
+            transport,
+            "POST",
+            "https://api.minecraftservices.com/authentication/login_with_xbox",
+            200,
+            json!({ "access_token": mc_access_token, "token_type": "Bearer", "expires_in": 86400 }),
+        );
+    }
+
+    fn get_body(request: &HttpRequest) -> Value {
+        serde_json::from_slice(request.body.as_ref().unwrap()).unwrap()
+    }
 
-    let resp: UserProfile = ureq::get(PROFILE_URL)
-        .set("Authorization", &format!("{tt} {at}"))
-        .call()?
-        .into_json()?;
+    #[test]
+    fn signs_in_through_xbox_live() {
+        let (_dir, launcher, transport) = get_launcher();
+        respond_sign_in(&transport, "mc-token");
 
-    Ok(resp)
+        let mca = launcher.get_minecraft_account("ms-token").unwrap();
+        assert_eq!(mca.access_token, "mc-token");
+        assert!(!mca.is_expired());
+
+        // every step passes on the token of the previous one
+        let requests = transport.get_requests();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(
+            get_body(&requests[0])["Properties"]["RpsTicket"],
+            "d=ms-token"
+        );
+        assert_eq!(
+            get_body(&requests[1])["Properties"]["UserTokens"],
+            json!(["xbl-token"])
+        );
+        assert_eq!(
+            get_body(&requests[2])["identityToken"],
+            "XBL3.0 x=user-hash;xsts-token"
+        );
+    }
+
+    #[test]
+    fn xsts_refusal_is_mapped() {
+        let (_dir, launcher, transport) = get_launcher();
+        respond_xbl(&transport);
+        respond_json(
+            &transport,
+            "POST",
+            "https://xsts.auth.xboxlive.com/xsts/authorize",
+            401,
+            json!({ "Identity": "0", "XErr": 2148916233u64, "Message": "", "Redirect": "" }),
+        );
+
+        let result = launcher.get_minecraft_account("ms-token");
+
+        assert!(matches!(result, Err(Error::Auth(AuthError::NoXboxAccount))));
+        // the minecraft services are never reached
+        assert_eq!(transport.get_requests().len(), 2);
+    }
+
+    #[test]
+    fn missing_profile_is_mapped() {
+        let (_dir, launcher, transport) = get_launcher();
+        respond_sign_in(&transport, "mc-token");
+        let mca = launcher.get_minecraft_account("ms-token").unwrap();
+
+        // no fixture for the profile, the transport answers 404
+        let result = launcher.get_user_profile(&mca);
+
+        assert!(matches!(
+            result,
+            Err(Error::Auth(AuthError::NoMinecraftProfile))
+        ));
+    }
+
+    #[test]
+    fn missing_user_hash_fails() {
+        let (_dir, launcher, transport) = get_launcher();
+        respond_xbl(&transport);
+        respond_json(
+            &transport,
+            "POST",
+            "https://xsts.auth.xboxlive.com/xsts/authorize",
+            200,
+            json!({ "Token": "xsts-token", "DisplayClaims": { "xui": [] } }),
+        );
+
+        let result = launcher.get_minecraft_account("ms-token");
+
+        assert!(matches!(
+            result,
+            Err(Error::Auth(AuthError::MissingUserHash))
+        ));
+    }
 }
@@ -0,0 +1,68 @@
+use std::path::Path;
+
+use url::Url;
+
+use crate::error::Error;
+
+/// Steps of Launcher::launch, reported before each one starts
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Phase {
+    Assets,
+    Libraries,
+    Natives,
+    Starting,
+}
+
+pub enum Event<'a> {
+    PhaseStarted(Phase),
+    /// sent before each batch of downloads, launch sends one for the asset index,
+    /// one for the assets and one for the libraries with the client jar.
+    /// total_bytes only counts files with a known size
+    DownloadsStarted {
+        files: usize,
+        total_bytes: u64,
+    },
+    FileStarted {
+        url: &'a Url,
+        path: &'a Path,
+    },
+    /// bytes received since the previous event for the same file,
+    /// a resumed download first reports the bytes already on disk
+    BytesTransferred {
+        url: &'a Url,
+        bytes: u64,
+    },
+    /// reused is true when a valid file was already present
+    FileFinished {
+        url: &'a Url,
+        path: &'a Path,
+        reused: bool,
+    },
+    /// a download failed with a transient error and is tried again
+    Retrying {
+        url: &'a Url,
+        attempt: u32,
+    },
+    FileFailed {
+        url: &'a Url,
+        error: &'a Error,
+    },
+}
+
+/// Receives the events of installs and downloads, possibly from several threads
+pub trait Progress: Sync {
+    fn report(&self, event: Event);
+}
+
+impl<F: Fn(Event) + Sync> Progress for F {
+    fn report(&self, event: Event) {
+        self(event)
+    }
+}
+
+/// Discards every event
+pub struct NoProgress;
+
+impl Progress for NoProgress {
+    fn report(&self, _event: Event) {}
+}
@@ -0,0 +1,110 @@
+use serde::Deserialize;
+
+use crate::{error::Result, loaders::LoaderVersion, runtime, Launcher};
+
+#[derive(Deserialize)]
+struct LoaderInfo {
+    version: String,
+}
+
+#[derive(Deserialize)]
+struct LoaderEntry {
+    loader: LoaderInfo,
+}
+
+impl Launcher {
+    pub(crate) async fn get_quilt_loader_versions_async(
+        &self,
+        minecraft_version: &str,
+    ) -> Result<Vec<LoaderVersion>> {
+        let meta_url = self.get_config().endpoints.quilt_meta;
+        let url = meta_url.join(&format!("versions/loader/{minecraft_version}"))?;
+        let resp: Vec<LoaderEntry> = self.get_json(&url).await?;
+
+        // quilt meta has no stable flag, pre-releases carry a -beta.N / -pre.N suffix
+        let versions = resp
+            .into_iter()
+            .map(|entry| LoaderVersion {
+                stable: !entry.loader.version.contains('-'),
+                version: entry.loader.version,
+            })
+            .collect();
+
+        Ok(versions)
+    }
+
+    /// returns the loader versions compatible with minecraft_version, newest first
+    pub fn get_quilt_loader_versions(&self, minecraft_version: &str) -> Result<Vec<LoaderVersion>> {
+        runtime::block_on(self.get_quilt_loader_versions_async(minecraft_version))
+    }
+
+    pub(crate) async fn download_quilt_profile_async(
+        &self,
+        minecraft_version: &str,
+        loader_version: &str,
+    ) -> Result<String> {
+        // quilt meta serves profiles at the same paths as fabric meta
+        let meta_url = self.get_config().endpoints.quilt_meta;
+        self.download_loader_profile(&meta_url, minecraft_version, loader_version)
+            .await
+    }
+
+    /// returns the profile version id
+    pub fn download_quilt_profile(
+        &self,
+        minecraft_version: &str,
+        loader_version: &str,
+    ) -> Result<String> {
+        runtime::block_on(self.download_quilt_profile_async(minecraft_version, loader_version))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use url::Url;
+
+    use crate::launcher::tests::get_launcher;
+
+    #[test]
+    fn suffixed_versions_are_unstable() {
+        let (_dir, launcher, transport) = get_launcher();
+        let url = Url::parse("https://meta.quiltmc.org/v3/versions/loader/1.20.4").unwrap();
+        let body = json!([
+            { "loader": { "version": "0.26.0-beta.1" } },
+            { "loader": { "version": "0.25.0" } },
+            { "loader": { "version": "0.24.0-pre.2" } }
+        ]);
+        transport.respond("GET", &url, 200, body.to_string());
+
+        let versions: Vec<(String, bool)> = launcher
+            .get_quilt_loader_versions("1.20.4")
+            .unwrap()
+            .into_iter()
+            .map(|version| (version.version, version.stable))
+            .collect();
+
+        assert_eq!(
+            versions,
+            [
+                ("0.26.0-beta.1".to_string(), false),
+                ("0.25.0".to_string(), true),
+                ("0.24.0-pre.2".to_string(), false)
+            ]
+        );
+    }
+
+    #[test]
+    fn profile_comes_from_quilt_meta() {
+        let (_dir, launcher, transport) = get_launcher();
+        let url =
+            Url::parse("https://meta.quiltmc.org/v3/versions/loader/1.20.4/0.25.0/profile/json")
+                .unwrap();
+        let profile = json!({ "id": "quilt-loader-0.25.0-1.20.4", "inheritsFrom": "1.20.4" });
+        transport.respond("GET", &url, 200, profile.to_string());
+
+        let version_id = launcher.download_quilt_profile("1.20.4", "0.25.0").unwrap();
+
+        assert_eq!(version_id, "quilt-loader-0.25.0-1.20.4");
+    }
+}
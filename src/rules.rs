@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+
+use regex::Regex;
+
+use crate::launchermeta::{Os, Rule, RuleAction};
+
+pub const IS_DEMO_USER: &str = "is_demo_user";
+pub const HAS_CUSTOM_RESOLUTION: &str = "has_custom_resolution";
+pub const HAS_QUICK_PLAYS_SUPPORT: &str = "has_quick_plays_support";
+pub const IS_QUICK_PLAY_SINGLEPLAYER: &str = "is_quick_play_singleplayer";
+pub const IS_QUICK_PLAY_MULTIPLAYER: &str = "is_quick_play_multiplayer";
+pub const IS_QUICK_PLAY_REALMS: &str = "is_quick_play_realms";
+
+/// Description of the host the rules are evaluated against
+pub struct Environment {
+    /// "windows", "osx" or "linux", as used in the version JSONs
+    pub os_name: String,
+    pub os_version: String,
+    /// "x86", "x86_64", "aarch64", ...
+    pub arch: String,
+    pub features: HashMap<String, bool>,
+}
+
+impl Environment {
+    /// returns the environment of the running host, with every feature disabled
+    pub fn current() -> Self {
+        Self {
+            os_name: std::env::consts::OS.replace("macos", "osx"),
+            os_version: os_info::get().version().to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            features: HashMap::new(),
+        }
+    }
+
+    pub fn with_feature(mut self, feature: &str, enabled: bool) -> Self {
+        self.features.insert(feature.to_string(), enabled);
+        self
+    }
+
+    pub fn has_feature(&self, feature: &str) -> bool {
+        self.features.get(feature).copied().unwrap_or(false)
+    }
+
+    /// returns "32" or "64", used by the ${arch} token in native classifiers
+    pub fn arch_bits(&self) -> &'static str {
+        match self.arch.as_str() {
+            "x86" | "arm" => "32",
+            _ => "64",
+        }
+    }
+}
+
+fn os_matches(os: &Os, env: &Environment) -> bool {
+    if let Some(name) = &os.name {
+        if name.ne(&env.os_name) {
+            return false;
+        }
+    }
+
+    if let Some(version) = &os.version {
+        match Regex::new(version) {
+            Ok(re) if re.is_match(&env.os_version) => {}
+            _ => return false,
+        }
+    }
+
+    if let Some(arch) = &os.arch {
+        if arch.ne(&env.arch) {
+            return false;
+        }
+    }
+
+    true
+}
+
+fn rule_matches(rule: &Rule, env: &Environment) -> bool {
+    if let Some(os) = &rule.os {
+        if !os_matches(os, env) {
+            return false;
+        }
+    }
+
+    if let Some(features) = &rule.features {
+        if features
+            .iter()
+            .any(|(feature, value)| env.has_feature(feature).ne(value))
+        {
+            return false;
+        }
+    }
+
+    true
+}
+
+/// Evaluates a rule list the way the vanilla launcher does:
+/// an empty list allows everything, otherwise everything is disallowed
+/// unless a matching rule allows it, and the last matching rule wins.
+pub fn is_allowed(rules: &[Rule], env: &Environment) -> bool {
+    if rules.is_empty() {
+        return true;
+    }
+
+    let mut action = RuleAction::Disallow;
+    for rule in rules {
+        if rule_matches(rule, env) {
+            action = rule.action;
+        }
+    }
+
+    action == RuleAction::Allow
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn get_rules(rules: serde_json::Value) -> Vec<Rule> {
+        serde_json::from_value(rules).unwrap()
+    }
+
+    fn get_env() -> Environment {
+        Environment {
+            os_name: "windows".to_string(),
+            os_version: "10.0".to_string(),
+            arch: "x86".to_string(),
+            features: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn empty_rules_allow() {
+        assert!(is_allowed(&[], &get_env()));
+    }
+
+    #[test]
+    fn last_matching_rule_wins() {
+        let rules = get_rules(json!([
+            { "action": "allow" },
+            { "action": "disallow", "os": { "name": "windows" } }
+        ]));
+        assert!(!is_allowed(&rules, &get_env()));
+
+        let rules = get_rules(json!([
+            { "action": "disallow", "os": { "name": "windows" } },
+            { "action": "allow" }
+        ]));
+        assert!(is_allowed(&rules, &get_env()));
+    }
+
+    #[test]
+    fn no_matching_rule_disallows() {
+        let rules = get_rules(json!([{ "action": "allow", "os": { "name": "osx" } }]));
+        assert!(!is_allowed(&rules, &get_env()));
+    }
+
+    #[test]
+    fn os_name() {
+        let rules = get_rules(json!([{ "action": "allow", "os": { "name": "windows" } }]));
+        assert!(is_allowed(&rules, &get_env()));
+
+        let env = Environment {
+            os_name: "linux".to_string(),
+            ..get_env()
+        };
+        assert!(!is_allowed(&rules, &env));
+    }
+
+    #[test]
+    fn os_version_regex() {
+        let rules = get_rules(json!([
+            { "action": "allow" },
+            { "action": "disallow", "os": { "name": "windows", "version": "^10\\." } }
+        ]));
+        assert!(!is_allowed(&rules, &get_env()));
+
+        let env = Environment {
+            os_version: "6.1".to_string(),
+            ..get_env()
+        };
+        assert!(is_allowed(&rules, &env));
+    }
+
+    #[test]
+    fn invalid_version_regex_never_matches() {
+        let rules = get_rules(json!([{ "action": "allow", "os": { "version": "^10\\.(" } }]));
+        assert!(!is_allowed(&rules, &get_env()));
+
+        let rules = get_rules(json!([
+            { "action": "allow" },
+            { "action": "disallow", "os": { "version": "[" } }
+        ]));
+        assert!(is_allowed(&rules, &get_env()));
+    }
+
+    #[test]
+    fn os_arch() {
+        let rules = get_rules(json!([{ "action": "allow", "os": { "arch": "x86" } }]));
+        assert!(is_allowed(&rules, &get_env()));
+
+        let env = Environment {
+            arch: "x86_64".to_string(),
+            ..get_env()
+        };
+        assert!(!is_allowed(&rules, &env));
+    }
+
+    #[test]
+    fn features() {
+        let rules = get_rules(json!([
+            { "action": "allow", "features": { "is_demo_user": true } }
+        ]));
+        assert!(!is_allowed(&rules, &get_env()));
+        assert!(is_allowed(
+            &rules,
+            &get_env().with_feature(IS_DEMO_USER, true)
+        ));
+
+        // every listed feature must have the expected value
+        let rules = get_rules(json!([{
+            "action": "allow",
+            "features": { "has_custom_resolution": true, "is_demo_user": false }
+        }]));
+        let env = get_env().with_feature(HAS_CUSTOM_RESOLUTION, true);
+        assert!(is_allowed(&rules, &env));
+        assert!(!is_allowed(&rules, &env.with_feature(IS_DEMO_USER, true)));
+    }
+}
@@ -0,0 +1,60 @@
+//! Runs the async implementation behind the blocking API
+
+#[cfg(feature = "tokio")]
+use std::panic;
+use std::{future::Future, time::Duration};
+
+#[cfg(feature = "tokio")]
+use tracing::Span;
+
+#[cfg(feature = "tokio")]
+lazy_static! {
+    /// drives the io and timers of blocking calls made outside of a tokio runtime
+    static ref RUNTIME: tokio::runtime::Runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .thread_name(env!("CARGO_PKG_NAME"))
+        .build()
+        .expect("error starting the tokio runtime");
+}
+
+/// Waits for future on the current thread. Inside a tokio runtime the ambient one is used,
+/// so blocking methods can be called from spawn_blocking but not from async code.
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    #[cfg(feature = "tokio")]
+    let _guard = match tokio::runtime::Handle::try_current() {
+        Ok(_) => None,
+        Err(_) => Some(RUNTIME.enter()),
+    };
+
+    futures_lite::future::block_on(future)
+}
+
+pub(crate) async fn sleep(duration: Duration) {
+    #[cfg(feature = "tokio")]
+    tokio::time::sleep(duration).await;
+
+    // without tokio every future is driven by block_on, blocking the thread is expected
+    #[cfg(not(feature = "tokio"))]
+    std::thread::sleep(duration);
+}
+
+/// Runs blocking filesystem work (hashing, writing, unzipping) on the tokio blocking pool,
+/// keeping the async threads free. Without tokio it runs in place, like every other io.
+pub(crate) async fn spawn_blocking<T, F>(f: F) -> T
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    #[cfg(feature = "tokio")]
+    {
+        // logs of the blocking work stay inside the caller span
+        let span = Span::current();
+        match tokio::task::spawn_blocking(move || span.in_scope(f)).await {
+            Ok(value) => value,
+            Err(e) => panic::resume_unwind(e.into_panic()),
+        }
+    }
+
+    #[cfg(not(feature = "tokio"))]
+    f()
+}